@@ -0,0 +1,245 @@
+//! Text console over bit banged serial
+//!
+//! [Console] wraps a serial port, e.g. a [Serial](crate::serial::Serial), for
+//! bring-up and debugging. It implements [core::fmt::Write], so `write!` and
+//! `writeln!` can be used for output, with every `\n` sent as `\r\n` for
+//! terminal emulators. [read_line](Console::read_line) reads a line of input
+//! with backspace handling and optional echo, which is enough for a simple
+//! interactive shell:
+//!
+//! ```ignore
+//! let mut console = Console::new(serial);
+//! let mut line = [0; 32];
+//! loop {
+//!     write!(console, "> ").unwrap();
+//!     let len = console.read_line(&mut line).unwrap();
+//!     writeln!(console, "got {} bytes", len).unwrap();
+//! }
+//! ```
+//!
+
+use embedded_hal::serial::{Read, Write};
+use nb::block;
+
+/// Backspace
+const BS: u8 = 0x08;
+/// Delete, sent by many terminals for the backspace key
+const DEL: u8 = 0x7F;
+/// Bell, sent when a line is full
+const BEL: u8 = 0x07;
+
+/// Line-oriented text console over a serial port
+pub struct Console<S> {
+    serial: S,
+    echo: bool,
+    after_cr: bool,
+}
+
+impl<S, E> Console<S>
+where
+    S: Read<u8, Error = E> + Write<u8, Error = E>,
+{
+    /// Create instance, with echo enabled
+    pub fn new(serial: S) -> Self {
+        Console {
+            serial,
+            echo: true,
+            after_cr: false,
+        }
+    }
+
+    /// Release the serial port
+    pub fn free(self) -> S {
+        self.serial
+    }
+
+    /// Echo received characters back while reading a line
+    ///
+    /// Enabled by default, as terminal emulators usually do not echo locally.
+    pub fn set_echo(&mut self, echo: bool) {
+        self.echo = echo;
+    }
+
+    /// Send `text`, translating `\n` to `\r\n`
+    pub fn write_text(&mut self, text: &str) -> Result<(), E> {
+        for byte in text.bytes() {
+            if byte == b'\n' {
+                block!(self.serial.write(b'\r'))?;
+            }
+            block!(self.serial.write(byte))?;
+        }
+        Ok(())
+    }
+
+    /// Send `line` followed by `\r\n`
+    pub fn write_line(&mut self, line: &str) -> Result<(), E> {
+        self.write_text(line)?;
+        self.write_text("\n")
+    }
+
+    /// Read a line of input into `buf`, returning its length
+    ///
+    /// Reading ends at `\r`, `\n` or `\r\n`, which is not stored; the `\n`
+    /// of an `\r\n` is skipped even when it arrives on the next call.
+    /// Backspace and delete remove the last character. Characters that do
+    /// not fit `buf` are dropped and answered with a bell. With echo enabled,
+    /// accepted characters are sent back, a backspace erases the character
+    /// on the terminal and the line end is echoed as `\r\n`.
+    pub fn read_line(&mut self, buf: &mut [u8]) -> Result<usize, E> {
+        let mut len = 0;
+        loop {
+            let byte = block!(self.serial.read())?;
+            let after_cr = core::mem::replace(&mut self.after_cr, byte == b'\r');
+            match byte {
+                b'\n' if after_cr => {}
+                b'\r' | b'\n' => {
+                    if self.echo {
+                        self.write_text("\n")?;
+                    }
+                    return Ok(len);
+                }
+                BS | DEL => {
+                    if len > 0 {
+                        len -= 1;
+                        if self.echo {
+                            for byte in [BS, b' ', BS] {
+                                block!(self.serial.write(byte))?;
+                            }
+                        }
+                    }
+                }
+                _ if len < buf.len() => {
+                    buf[len] = byte;
+                    len += 1;
+                    if self.echo {
+                        block!(self.serial.write(byte))?;
+                    }
+                }
+                _ => block!(self.serial.write(BEL))?,
+            }
+        }
+    }
+}
+
+impl<S, E> core::fmt::Write for Console<S>
+where
+    S: Read<u8, Error = E> + Write<u8, Error = E>,
+{
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        self.write_text(s).map_err(|_| core::fmt::Error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::convert::Infallible;
+
+    /// Serial port replaying `input` and recording writes
+    struct Script<'a> {
+        input: &'a [u8],
+        output: [u8; 32],
+        written: usize,
+    }
+
+    impl<'a> Script<'a> {
+        fn new(input: &'a [u8]) -> Self {
+            Script {
+                input,
+                output: [0; 32],
+                written: 0,
+            }
+        }
+    }
+
+    impl Read<u8> for Script<'_> {
+        type Error = Infallible;
+
+        fn read(&mut self) -> nb::Result<u8, Infallible> {
+            let (byte, rest) = self.input.split_first().ok_or(nb::Error::WouldBlock)?;
+            self.input = rest;
+            Ok(*byte)
+        }
+    }
+
+    impl Write<u8> for Script<'_> {
+        type Error = Infallible;
+
+        fn write(&mut self, word: u8) -> nb::Result<(), Infallible> {
+            self.output[self.written] = word;
+            self.written += 1;
+            Ok(())
+        }
+
+        fn flush(&mut self) -> nb::Result<(), Infallible> {
+            Ok(())
+        }
+    }
+
+    fn echoed<'a>(console: &'a Console<Script>) -> &'a [u8] {
+        &console.serial.output[..console.serial.written]
+    }
+
+    #[test]
+    fn read_line_ends_at_cr_lf_or_crlf() {
+        let mut console = Console::new(Script::new(b"ab\rcd\nef\r\ngh\r"));
+        console.set_echo(false);
+        let mut buf = [0; 8];
+        for line in [&b"ab"[..], b"cd", b"ef", b"gh"] {
+            let len = console.read_line(&mut buf).unwrap();
+            assert_eq!(&buf[..len], line);
+        }
+    }
+
+    #[test]
+    fn read_line_skips_lf_of_crlf_on_next_call() {
+        let mut console = Console::new(Script::new(b"ab\r"));
+        console.set_echo(false);
+        let mut buf = [0; 8];
+        assert_eq!(console.read_line(&mut buf), Ok(2));
+        // the LF of the CR+LF arrives only now
+        console.serial.input = b"\ncd\n\n";
+        let len = console.read_line(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"cd");
+        // a lone LF is an empty line
+        assert_eq!(console.read_line(&mut buf), Ok(0));
+    }
+
+    #[test]
+    fn read_line_echoes_backspace_and_delete() {
+        let mut console = Console::new(Script::new(b"\x08ab\x08c\x7f\x7f\x7fd\r"));
+        let mut buf = [0; 8];
+        let len = console.read_line(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"d");
+        // nothing is echoed for a backspace on an empty line
+        assert_eq!(echoed(&console), b"ab\x08 \x08c\x08 \x08\x08 \x08d\r\n");
+    }
+
+    #[test]
+    fn read_line_rings_bell_on_overflow() {
+        let mut console = Console::new(Script::new(b"abcd\x08e\r"));
+        let mut buf = [0; 2];
+        let len = console.read_line(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"ae");
+        assert_eq!(echoed(&console), b"ab\x07\x07\x08 \x08e\r\n");
+    }
+
+    #[test]
+    fn read_line_without_echo_sends_nothing() {
+        let mut console = Console::new(Script::new(b"ab\x08\x7fcde\r"));
+        console.set_echo(false);
+        let mut buf = [0; 2];
+        let len = console.read_line(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"cd");
+        // overflow still rings the bell
+        assert_eq!(echoed(&console), b"\x07");
+    }
+
+    #[test]
+    fn write_text_translates_newlines() {
+        let mut console = Console::new(Script::new(b""));
+        console.write_text("a\nb").unwrap();
+        console.write_line("c").unwrap();
+        assert_eq!(echoed(&console), b"a\r\nbc\r\n");
+    }
+}
@@ -0,0 +1,72 @@
+//! Checksums and CRCs used by bus protocols
+//!
+//! Table-free implementations for `no_std`, so they can be used to compute
+//! matching checksums for payloads sent through the drivers in this crate.
+//!
+
+/// CRC-8 as used for the SMBus packet error code (PEC)
+///
+/// Polynomial x⁸ + x² + x + 1 (0x07), MSB first, no reflection and no final
+/// XOR. Start with a `crc` of 0 and feed the data in one or more slices,
+/// passing the previous result as `crc`, e.g. the address byte followed by
+/// the command and data. `crc8_smbus(0, b"123456789")` is 0xF4.
+pub fn crc8_smbus(crc: u8, data: &[u8]) -> u8 {
+    data.iter().fold(crc, |crc, byte| {
+        (0..8).fold(crc ^ byte, |crc, _| {
+            if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x07
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+/// XOR of all bytes
+pub fn xor_sum(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, byte| sum ^ byte)
+}
+
+/// LIN frame checksum
+///
+/// The inverted 8-bit sum with end-around carry of the data bytes. With a
+/// protected identifier `pid` the enhanced checksum of LIN 2.x is computed,
+/// which includes it; with `None` the classic checksum of LIN 1.x.
+pub fn lin_checksum(pid: Option<u8>, data: &[u8]) -> u8 {
+    let sum = pid.iter().chain(data).fold(0u8, |sum, byte| {
+        let (sum, carry) = sum.overflowing_add(*byte);
+        sum + carry as u8
+    });
+    !sum
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc8_smbus_check_value() {
+        assert_eq!(crc8_smbus(0, b"123456789"), 0xf4);
+        assert_eq!(crc8_smbus(crc8_smbus(0, b"1234"), b"56789"), 0xf4);
+    }
+
+    #[test]
+    fn xor_sum_of_bytes() {
+        assert_eq!(xor_sum(&[]), 0);
+        assert_eq!(xor_sum(&[0x12, 0x34, 0x56]), 0x70);
+    }
+
+    // the checksum example of the LIN 2.x specification sums the bytes
+    // 0x4A, 0x55, 0x93 and 0xE5 to 0x19, giving the checksum 0xE6
+
+    #[test]
+    fn lin_classic_checksum() {
+        assert_eq!(lin_checksum(None, &[0x4a, 0x55, 0x93, 0xe5]), 0xe6);
+    }
+
+    #[test]
+    fn lin_enhanced_checksum() {
+        assert_eq!(lin_checksum(Some(0x4a), &[0x55, 0x93, 0xe5]), 0xe6);
+        assert_ne!(lin_checksum(None, &[0x55, 0x93, 0xe5]), 0xe6);
+    }
+}
@@ -0,0 +1,206 @@
+//! Type-erased driver errors
+//!
+//! The error types of the drivers are generic over the GPIO error, so code
+//! holding several buses ends up with as many error types. [ErasedError]
+//! keeps the kind of error and its message in a fixed size value without
+//! that parameter, so errors from different buses can be collected, logged
+//! or stored together.
+//!
+//! ```ignore
+//! fn update(i2c: &mut I2c, spi: &mut Spi) -> Result<(), ErasedError> {
+//!     i2c.write(0x50, &[0x00, 0x01])?;
+//!     spi.write(&[0x9f])?;
+//!     Ok(())
+//! }
+//! ```
+//!
+
+use core::fmt::{self, Debug, Display, Write};
+
+/// Maximum length of an [ErasedError] message in bytes
+pub const MESSAGE_LEN: usize = 32;
+
+/// Driver independent kind of an [ErasedError]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ErrorKind {
+    /// GPIO error
+    Bus,
+    /// No ack received
+    NoAck,
+    /// Invalid input
+    InvalidData,
+    /// Device did not respond in time
+    Timeout,
+    /// Another master won arbitration
+    ArbitrationLost,
+    /// Transfer cancelled through the abort flag
+    Aborted,
+    /// Attempted read without input data
+    NoData,
+    /// Received frame checksum does not match
+    Checksum,
+    /// Frame does not fit the buffer or exceeds the maximum length
+    Overflow,
+    /// Break received
+    Break,
+}
+
+/// Error of any driver in this crate, without the GPIO error type
+///
+/// Created with `From` from the driver errors. The message is the `Display`
+/// output of the original error, which includes the `Debug` output of a GPIO
+/// error, cut to [MESSAGE_LEN] bytes.
+#[derive(Clone, Copy)]
+pub struct ErasedError {
+    kind: ErrorKind,
+    message: [u8; MESSAGE_LEN],
+    message_len: usize,
+}
+
+impl ErasedError {
+    fn new(kind: ErrorKind, error: &dyn Display) -> Self {
+        let mut erased = ErasedError {
+            kind,
+            message: [0; MESSAGE_LEN],
+            message_len: 0,
+        };
+        // a message that does not fit is cut, not an error
+        write!(erased, "{}", error).ok();
+        erased
+    }
+
+    /// Kind of the original error
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    /// Message of the original error, possibly cut short
+    pub fn message(&self) -> &str {
+        // only whole characters are ever copied into the buffer
+        core::str::from_utf8(&self.message[..self.message_len]).unwrap_or("")
+    }
+}
+
+/// Write a GPIO error, and the line it occurred on if known, for the
+/// `Display` impls of the driver errors
+///
+/// The GPIO error is printed with `Debug`: the `embedded-hal` pin traits only
+/// require `Debug` of their error types, and many HALs implement nothing
+/// else, so requiring `Display` would leave their drivers without one.
+pub(crate) fn fmt_bus_error(
+    f: &mut fmt::Formatter<'_>,
+    line: Option<&dyn Debug>,
+    error: &dyn Debug,
+) -> fmt::Result {
+    match line {
+        Some(line) => write!(f, "bus error on {:?}: {:?}", line, error),
+        None => write!(f, "bus error: {:?}", error),
+    }
+}
+
+impl Write for ErasedError {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            let len = c.len_utf8();
+            if self.message_len + len > MESSAGE_LEN {
+                return Err(fmt::Error);
+            }
+            c.encode_utf8(&mut self.message[self.message_len..]);
+            self.message_len += len;
+        }
+        Ok(())
+    }
+}
+
+impl Debug for ErasedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ErasedError")
+            .field("kind", &self.kind)
+            .field("message", &self.message())
+            .finish()
+    }
+}
+
+impl Display for ErasedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
+#[cfg(feature = "core-error")]
+impl core::error::Error for ErasedError {}
+
+impl PartialEq for ErasedError {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind == other.kind && self.message() == other.message()
+    }
+}
+
+impl Eq for ErasedError {}
+
+impl<E> From<crate::i2c::Error<E>> for ErasedError
+where
+    E: Debug,
+{
+    fn from(error: crate::i2c::Error<E>) -> Self {
+        use crate::i2c::Error;
+        let kind = match error {
+            Error::Bus(_) => ErrorKind::Bus,
+            Error::NoAck => ErrorKind::NoAck,
+            Error::InvalidData => ErrorKind::InvalidData,
+            Error::Timeout => ErrorKind::Timeout,
+            Error::ArbitrationLost => ErrorKind::ArbitrationLost,
+            Error::Aborted => ErrorKind::Aborted,
+        };
+        ErasedError::new(kind, &error)
+    }
+}
+
+impl<E> From<crate::spi::Error<E>> for ErasedError
+where
+    E: Debug,
+{
+    fn from(error: crate::spi::Error<E>) -> Self {
+        use crate::spi::Error;
+        let kind = match error {
+            Error::Bus(..) => ErrorKind::Bus,
+            Error::NoData => ErrorKind::NoData,
+            Error::Aborted => ErrorKind::Aborted,
+        };
+        ErasedError::new(kind, &error)
+    }
+}
+
+impl<E> From<crate::serial::Error<E>> for ErasedError
+where
+    E: Debug,
+{
+    fn from(error: crate::serial::Error<E>) -> Self {
+        use crate::serial::Error;
+        let kind = match error {
+            Error::Bus(_) => ErrorKind::Bus,
+            Error::Checksum => ErrorKind::Checksum,
+            Error::Overflow => ErrorKind::Overflow,
+            Error::Break => ErrorKind::Break,
+        };
+        ErasedError::new(kind, &error)
+    }
+}
+
+impl<E> From<crate::ws2812::Error<E>> for ErasedError
+where
+    E: Debug,
+{
+    fn from(error: crate::ws2812::Error<E>) -> Self {
+        ErasedError::new(ErrorKind::Bus, &error)
+    }
+}
+
+impl<E> From<crate::ir::Error<E>> for ErasedError
+where
+    E: Debug,
+{
+    fn from(error: crate::ir::Error<E>) -> Self {
+        ErasedError::new(ErrorKind::Bus, &error)
+    }
+}
@@ -5,12 +5,31 @@
   - A periodic timer to mark clock cycles
   - Two GPIO pins for SDA and SCL lines.
 
-  Note that the current implementation does not support I2C clock stretching.
+  Clock stretching by slaves is tolerated if SCL can also be read, see
+  [I2cBB::set_clock_stretch_timeout]. Otherwise SCL is assumed to follow the
+  driven level. With a readable SCL the driver can also share the bus with
+  other masters, see [I2cBB::set_arbitration_check].
+
+  ## Empty buffers
+
+  Empty buffers are handled the same way by [Write], [Read] and [WriteRead]:
+
+  - An empty write still addresses the device and checks its ACK, so
+    `write(addr, &[])` is a quick command or presence check.
+  - An empty read is skipped, since a read cannot end without clocking in a
+    byte. `read(addr, &mut [])` does not touch the bus.
+
+  `write_read(addr, &[], input)` therefore addresses the device for writing
+  before the repeated START and the read, and `write_read(addr, output, &mut
+  [])` is the same as `write(addr, output)`.
 
   ## Hardware requirements
 
   1. Configure GPIO pins as Open-Drain outputs.
-  2. Configure timer frequency to be twice the desired I2C clock frequency.
+  2. Configure timer frequency to be twice the desired I2C clock frequency,
+     see [recommended_timer_hz]. Rise and settle ticks lengthen the bits, so
+     with them the bus runs slower than that; [I2cBB::calibrate] produces a
+     clock on SCL to check the result with a frequency counter.
 
   ## Blue Pill example
 
@@ -50,10 +69,15 @@
   ```
 */
 
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use embedded_hal::blocking::delay::DelayUs;
 use embedded_hal::blocking::i2c::{Read, Write, WriteRead};
 use embedded_hal::digital::v2::{InputPin, OutputPin};
 use embedded_hal::timer::{CountDown, Periodic};
-use nb::block;
+
+use crate::spi::BitOrder;
+use crate::timing::{close_to, half_period_wait, DelayTimer};
 
 /// I2C error
 #[derive(Debug, Eq, PartialEq)]
@@ -64,6 +88,201 @@ pub enum Error<E> {
     NoAck,
     /// Invalid input
     InvalidData,
+    /// Device did not respond in time
+    Timeout,
+    /// Another master won arbitration; both lines have been released
+    ArbitrationLost,
+    /// Transfer cancelled through the abort flag
+    Aborted,
+}
+
+impl<E> core::fmt::Display for Error<E>
+where
+    E: core::fmt::Debug,
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Error::Bus(e) => crate::error::fmt_bus_error(f, None, e),
+            Error::NoAck => f.write_str("no ack"),
+            Error::InvalidData => f.write_str("invalid data"),
+            Error::Timeout => f.write_str("timeout"),
+            Error::ArbitrationLost => f.write_str("arbitration lost"),
+            Error::Aborted => f.write_str("aborted"),
+        }
+    }
+}
+
+#[cfg(feature = "core-error")]
+impl<E> core::error::Error for Error<E>
+where
+    E: core::error::Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            Error::Bus(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// A pin whose direction can be switched at runtime
+///
+/// Implement this for GPIOs without a real open-drain mode to use them as SDA
+/// through [Tristate].
+pub trait TristatePin {
+    /// Error type
+    type Error;
+
+    /// Switch the pin to input (high impedance), releasing the line
+    fn set_input(&mut self) -> Result<(), Self::Error>;
+
+    /// Switch the pin to output, driving the line low
+    fn set_output_low(&mut self) -> Result<(), Self::Error>;
+}
+
+/// Open-drain emulation for push-pull-only GPIOs
+///
+/// Releasing the line (`set_high`) switches the pin to input so the pull-up
+/// raises it, driving it low (`set_low`) switches it to output low. The line
+/// is therefore never actively driven high. See [I2cBB::new_tristate].
+pub struct Tristate<P> {
+    pin: P,
+}
+
+impl<P> Tristate<P> {
+    /// Create instance
+    pub fn new(pin: P) -> Self {
+        Tristate { pin }
+    }
+
+    /// Release the pin
+    pub fn free(self) -> P {
+        self.pin
+    }
+}
+
+impl<P, E> OutputPin for Tristate<P>
+where
+    P: TristatePin<Error = E>,
+{
+    type Error = E;
+
+    fn set_low(&mut self) -> Result<(), E> {
+        self.pin.set_output_low()
+    }
+
+    fn set_high(&mut self) -> Result<(), E> {
+        self.pin.set_input()
+    }
+}
+
+impl<P, E> InputPin for Tristate<P>
+where
+    P: InputPin<Error = E>,
+{
+    type Error = E;
+
+    fn is_high(&self) -> Result<bool, E> {
+        self.pin.is_high()
+    }
+
+    fn is_low(&self) -> Result<bool, E> {
+        self.pin.is_low()
+    }
+}
+
+/// Ticks waited per failed attempt by [I2cBB::with_retry], about one byte
+const RETRY_BACKOFF_TICKS: u32 = 18;
+
+/// Timer frequency to program for an I2C clock of `target_i2c_hz`
+///
+/// Each SCL period takes two timer ticks, one high and one low, so the timer
+/// has to run at twice the bus clock. Extra ticks such as
+/// [I2cBB::set_rise_ticks] come on top and lower the effective bus clock.
+pub const fn recommended_timer_hz(target_i2c_hz: u32) -> u32 {
+    target_i2c_hz.saturating_mul(2)
+}
+
+/// I2C bus speed timing presets
+///
+/// Presets assume the timer runs at twice the bus clock as described in the
+/// module documentation and select the START/STOP hold ticks and the rise
+/// time allowance, see [I2cBB::set_timing_preset].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TimingPreset {
+    /// Standard mode (100 kHz): single tick holds, no rise allowance
+    Standard,
+    /// Fast mode (400 kHz): single tick holds, one tick rise allowance
+    Fast,
+    /// Fast mode plus (1 MHz): two tick holds, one tick rise allowance
+    FastPlus,
+    /// Long cables or other high-capacitance buses: two tick holds, two tick
+    /// rise allowance and one settle tick after every line release
+    ///
+    /// Slow rising edges are given time to settle at the cost of about
+    /// half the throughput of [Standard](TimingPreset::Standard) at the same
+    /// timer frequency.
+    LongBus,
+}
+
+impl TimingPreset {
+    /// `(hold_ticks, rise_ticks, settle_ticks)` of the preset
+    fn ticks(self) -> (u32, u32, u32) {
+        match self {
+            TimingPreset::Standard => (1, 0, 0),
+            TimingPreset::Fast => (1, 1, 0),
+            TimingPreset::FastPlus => (2, 1, 0),
+            TimingPreset::LongBus => (2, 2, 1),
+        }
+    }
+}
+
+/// Result of [I2cBB::bus_health]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct BusHealth {
+    /// SCL was high at idle
+    pub scl_idle_high: bool,
+    /// SDA was high at idle
+    pub sda_idle_high: bool,
+    /// SDA was stuck low and [I2cBB::recover_bus] was run
+    pub recovery_needed: bool,
+    /// The recovery released SDA; `false` if it was not needed
+    pub recovered: bool,
+    /// A START and STOP drove SDA low and released it again
+    pub start_stop_ok: bool,
+}
+
+impl BusHealth {
+    /// Whether the bus was idle and a START/STOP completed cleanly
+    pub fn is_healthy(&self) -> bool {
+        self.scl_idle_high && self.sda_idle_high && self.start_stop_ok
+    }
+}
+
+/// Clock phase of a non-blocking transaction
+#[derive(Clone, Copy)]
+enum Step {
+    /// START condition, sub-phase 0..=2
+    Start(u8),
+    /// Clocking out byte `index` (0 is the address), `bit` 8 is the ACK bit
+    WriteBit { index: usize, bit: u8, high: bool },
+    /// Clocking in byte `index`, `bit` 8 is the ACK bit
+    ReadBit { index: usize, bit: u8, high: bool },
+    /// STOP condition, sub-phase 0..=1
+    Stop(u8),
+}
+
+/// Reads back whether SCL has risen, for clock stretch detection
+type SclReleased<SCL> = fn(&SCL) -> Result<bool, <SCL as OutputPin>::Error>;
+
+/// State of an ongoing non-blocking transaction
+struct Transfer {
+    step: Option<Step>,
+    ticks_left: u32,
+    byte: u8,
+    nack: bool,
+    /// The repeated START of a write-read has been sent
+    restarted: bool,
 }
 
 /// Bit banging I2C device
@@ -75,7 +294,24 @@ where
 {
     scl: SCL,
     sda: SDA,
-    clk: CLK,
+    clk: Option<CLK>,
+    start_hold_ticks: u32,
+    stop_hold_ticks: u32,
+    rise_ticks: u32,
+    scl_level: bool,
+    tick_count: u32,
+    partial_byte: u8,
+    partial_bits: u8,
+    transfer: Option<Transfer>,
+    scl_released: Option<SclReleased<SCL>>,
+    stretch_timeout_ticks: u32,
+    bit_order: BitOrder,
+    inter_byte_ticks: u32,
+    check_arbitration: bool,
+    abort: Option<&'static AtomicBool>,
+    sample_count: u8,
+    settle_ticks: u32,
+    configured_hz: u32,
 }
 
 impl<SCL, SDA, CLK, E> I2cBB<SCL, SDA, CLK>
@@ -86,7 +322,220 @@ where
 {
     /// Create instance
     pub fn new(scl: SCL, sda: SDA, clk: CLK) -> Self {
-        I2cBB { scl, sda, clk }
+        Self::new_with_hold_ticks(scl, sda, clk, 1, 1)
+    }
+
+    /// Create instance, checking the timer frequency
+    ///
+    /// `bus_hz` is the intended I2C clock and `timer_hz` the frequency the
+    /// timer was started with.
+    ///
+    /// # Panics
+    ///
+    /// In debug builds, if `timer_hz` is not within 10 % of
+    /// [recommended_timer_hz(bus_hz)](recommended_timer_hz), e.g. when the
+    /// timer was started at the bus clock instead of twice that. Release
+    /// builds skip the check.
+    pub fn new_checked(scl: SCL, sda: SDA, clk: CLK, bus_hz: u32, timer_hz: u32) -> Self {
+        debug_assert!(
+            close_to(timer_hz, recommended_timer_hz(bus_hz)),
+            "timer must run at twice the I2C clock"
+        );
+        let mut i2c = Self::new(scl, sda, clk);
+        i2c.configured_hz = timer_hz;
+        i2c
+    }
+
+    /// Create instance with custom START and STOP condition timing
+    ///
+    /// `start_hold_ticks` is the number of timer ticks SDA is held low before
+    /// SCL falls in a START (tHD;STA), `stop_hold_ticks` the number of ticks
+    /// SCL is held high before SDA rises in a STOP (tSU;STO). [new](Self::new)
+    /// uses a single tick for both.
+    pub fn new_with_hold_ticks(
+        scl: SCL,
+        sda: SDA,
+        clk: CLK,
+        start_hold_ticks: u32,
+        stop_hold_ticks: u32,
+    ) -> Self {
+        I2cBB {
+            scl,
+            sda,
+            clk: Some(clk),
+            start_hold_ticks,
+            stop_hold_ticks,
+            rise_ticks: 0,
+            scl_level: true,
+            tick_count: 0,
+            partial_byte: 0,
+            partial_bits: 0,
+            transfer: None,
+            scl_released: None,
+            stretch_timeout_ticks: 0,
+            bit_order: BitOrder::default(),
+            inter_byte_ticks: 0,
+            check_arbitration: false,
+            abort: None,
+            sample_count: 1,
+            settle_ticks: 0,
+            configured_hz: 0,
+        }
+    }
+
+    /// Apply a bus speed timing preset
+    pub fn set_timing_preset(&mut self, preset: TimingPreset) {
+        let (hold_ticks, rise_ticks, settle_ticks) = preset.ticks();
+        self.start_hold_ticks = hold_ticks;
+        self.stop_hold_ticks = hold_ticks;
+        self.rise_ticks = rise_ticks;
+        self.settle_ticks = settle_ticks;
+    }
+
+    /// Set the number of extra ticks to wait after releasing SCL or SDA
+    ///
+    /// On long cables the bus capacitance slows the rising edges, so every
+    /// release of a line is followed by this wait before the next edge or
+    /// sample. Each tick lengthens every bit, so keep it as low as the bus
+    /// allows. The non-blocking API blocks during the wait. Defaults to zero.
+    pub fn set_settle_ticks(&mut self, ticks: u32) {
+        self.settle_ticks = ticks;
+    }
+
+    /// Set the number of extra ticks to wait before sampling SDA
+    ///
+    /// After SDA has been released and SCL raised, the line needs time to
+    /// rise through the pull-up before it can be read reliably. This wait is
+    /// inserted before every ACK and data bit sample. Defaults to zero.
+    pub fn set_rise_ticks(&mut self, ticks: u32) {
+        self.rise_ticks = ticks;
+    }
+
+    /// Set the number of extra ticks SCL is held low after each byte
+    ///
+    /// The gap follows the ACK bit of every byte, including the address,
+    /// which makes bytes easy to tell apart on a logic analyzer and gives
+    /// marginal slaves extra time. Defaults to zero.
+    pub fn set_inter_byte_ticks(&mut self, ticks: u32) {
+        self.inter_byte_ticks = ticks;
+    }
+
+    /// Cancel transactions when `flag` is set, e.g. from an interrupt handler
+    ///
+    /// The flag is checked before every byte of the blocking API, so a
+    /// transaction in progress ends after the current byte with a STOP and
+    /// `Err(Aborted)`. It stays set until the application clears it, and
+    /// while set every transaction fails. `None` (the default) disables the
+    /// check. The non-blocking API can be cancelled by no longer polling it
+    /// and calling [stop](Self::stop).
+    pub fn set_abort_flag(&mut self, flag: Option<&'static AtomicBool>) {
+        self.abort = flag;
+    }
+
+    /// Read SDA `count` times per bit and take the majority
+    ///
+    /// Improves noise immunity of ACK and data reads on noisy boards. The
+    /// reads follow each other directly after the usual sampling point, so
+    /// SCL stays high slightly longer. A tie with an even count reads as low.
+    /// The default of 1 samples once; 0 is treated as 1.
+    pub fn set_sample_count(&mut self, count: u8) {
+        self.sample_count = count.max(1);
+    }
+
+    /// Set the bit order of transmitted and received bytes, including the
+    /// address byte
+    ///
+    /// I2C is defined MSB-first, which is the default. LSB-first is a
+    /// non-standard escape hatch for I2C-like devices that shift data the
+    /// other way round; do not use it with standard I2C devices.
+    pub fn set_bit_order(&mut self, order: BitOrder) {
+        self.bit_order = order;
+    }
+
+    /// Allows for an access to the timer type.
+    /// This can be used to change the speed.
+    ///
+    /// In closure you get ownership of the timer
+    /// so you can destruct it and build it up again if necessary.
+    ///
+    /// # Example
+    ///
+    /// ```Rust
+    ///i2c.access_timer(|mut timer| {
+    ///    timer.set_freq(400.khz());
+    ///    timer
+    ///});
+    ///```
+    ///
+    pub fn access_timer<F>(&mut self, f: F)
+    where
+        F: FnOnce(CLK) -> CLK,
+    {
+        if let Some(timer) = self.clk.take() {
+            self.clk = Some(f(timer));
+        }
+    }
+
+    /// Replace the timer, dropping the current one.
+    ///
+    /// Use [access_timer](Self::access_timer) to reconfigure the existing
+    /// timer instead.
+    pub fn set_timer(&mut self, timer: CLK) {
+        self.clk = Some(timer);
+    }
+
+    /// Record the timer frequency in Hz for [protocol_hz](Self::protocol_hz)
+    pub fn set_configured_hz(&mut self, hz: u32) {
+        self.configured_hz = hz;
+    }
+
+    /// Approximate SCL frequency in Hz, half the configured timer frequency
+    ///
+    /// Hold, rise and settle ticks and clock stretching make the actual rate
+    /// lower. Returns 0 if no timer frequency was recorded.
+    pub fn protocol_hz(&self) -> u32 {
+        self.configured_hz / 2
+    }
+
+    /// Return the number of timer ticks waited since the last call and reset
+    /// the counter.
+    ///
+    /// This allows checking how long a transaction takes, e.g. a one byte
+    /// write, to verify the timer configuration without an oscilloscope.
+    pub fn take_tick_count(&mut self) -> u32 {
+        core::mem::replace(&mut self.tick_count, 0)
+    }
+
+    /// Take the bits of a byte whose read was cut short, as `(value, count)`
+    ///
+    /// When an error interrupts receiving a byte, `count` is the number of
+    /// bits clocked in before it and `value` holds them at their positions
+    /// in the byte, the other bits zero. A `count` of 8 means the byte was
+    /// complete but its ACK bit failed. After a successful read `count` is 0.
+    /// Both are reset by this call.
+    pub fn take_partial(&mut self) -> (u8, u8) {
+        let value = core::mem::replace(&mut self.partial_byte, 0);
+        let count = core::mem::replace(&mut self.partial_bits, 0);
+        (value, count)
+    }
+
+    /// Toggle SCL for `cycles` clock periods to measure the bus clock.
+    ///
+    /// SDA is released and no START is sent, so slaves ignore the pulses.
+    /// Each period is one tick high and one tick low plus any settle ticks,
+    /// the same as a written data bit, so a frequency counter or oscilloscope
+    /// on SCL shows the bus clock the timer actually produces. Returns the
+    /// number of timer ticks used. SCL is left high.
+    pub fn calibrate(&mut self, cycles: u32) -> Result<u32, crate::i2c::Error<E>> {
+        let start = self.tick_count;
+        self.set_sda_high()?;
+        for _ in 0..cycles {
+            self.set_scl_low()?;
+            self.wait_for_clk();
+            self.set_scl_high()?;
+            self.wait_for_clk();
+        }
+        Ok(self.tick_count.wrapping_sub(start))
     }
 
     /// Send a raw I2C start.
@@ -95,12 +544,14 @@ where
     /// please use the embedded-hal traits [Read], [Write], or
     /// [WriteRead].
     pub fn raw_i2c_start(&mut self) -> Result<(), crate::i2c::Error<E>> {
-        self.set_scl_high()?;
+        // release SDA first, so a repeated START does not raise SDA while
+        // SCL is high, which would be a STOP
         self.set_sda_high()?;
+        self.set_scl_high()?;
         self.wait_for_clk();
 
         self.set_sda_low()?;
-        self.wait_for_clk();
+        self.wait_for_clk_ticks(self.start_hold_ticks);
 
         self.set_scl_low()?;
         self.wait_for_clk();
@@ -115,7 +566,7 @@ where
     /// [WriteRead].
     pub fn raw_i2c_stop(&mut self) -> Result<(), crate::i2c::Error<E>> {
         self.set_scl_high()?;
-        self.wait_for_clk();
+        self.wait_for_clk_ticks(self.stop_hold_ticks);
 
         self.set_sda_high()?;
         self.wait_for_clk();
@@ -123,184 +574,1340 @@ where
         Ok(())
     }
 
-    fn i2c_is_ack(&mut self) -> Result<bool, crate::i2c::Error<E>> {
-        self.set_sda_high()?;
-        self.set_scl_high()?;
-        self.wait_for_clk();
-
-        let ack = self.sda.is_low().map_err(Error::Bus)?;
-
-        self.set_scl_low()?;
-        self.set_sda_low()?;
-        self.wait_for_clk();
-
-        Ok(ack)
+    /// Check whether a device acknowledges its address.
+    ///
+    /// Sends a START, the address with the write bit and a STOP, leaving the
+    /// bus idle. Unlike [Write], a missing ACK is reported as `Ok(false)`
+    /// rather than `Err(NoAck)`, so only real bus errors are returned as errors.
+    pub fn is_present(&mut self, addr: u8) -> Result<bool, crate::i2c::Error<E>> {
+        self.transaction(|bus| {
+            // ST
+            bus.raw_i2c_start()?;
+
+            // SAD + W
+            bus.i2c_write_byte(addr << 1)?;
+            bus.i2c_is_ack()
+        })
     }
 
-    fn i2c_read_byte(&mut self, should_send_ack: bool) -> Result<u8, crate::i2c::Error<E>> {
-        let mut byte: u8 = 0;
-
-        self.set_sda_high()?;
-
-        for bit_offset in 0..8 {
-            self.set_scl_high()?;
-            self.wait_for_clk();
+    /// Broadcast a write to the general call address (0x00).
+    ///
+    /// The first data byte selects the general call command, e.g. `0x06` for
+    /// a software reset. Returns `Err(NoAck)` if no device acknowledged the
+    /// general call.
+    pub fn general_call(&mut self, data: &[u8]) -> Result<(), crate::i2c::Error<E>> {
+        self.transaction(|bus| {
+            // ST
+            bus.raw_i2c_start()?;
+
+            // General call address + W
+            bus.i2c_write_byte(0x00)?;
+            bus.check_ack()?;
+
+            bus.raw_write_to_slave(data)
+        })
+    }
 
-            if self.sda.is_high().map_err(Error::Bus)? {
-                byte |= 1 << (7 - bit_offset);
+    /// Write to a device, then wait until it finishes its internal write cycle.
+    ///
+    /// EEPROMs and similar devices do not acknowledge their address while an
+    /// internal write cycle is in progress. After the write, the address is
+    /// polled with [is_present](Self::is_present) up to `max_poll` times until
+    /// the device ACKs again. Returns `Err(Timeout)` if it never does.
+    pub fn write_then_wait_ready(
+        &mut self,
+        addr: u8,
+        data: &[u8],
+        max_poll: u32,
+    ) -> Result<(), crate::i2c::Error<E>> {
+        self.write(addr, data)?;
+
+        for _ in 0..max_poll {
+            if self.is_present(addr)? {
+                return Ok(());
             }
-
-            self.set_scl_low()?;
-            self.wait_for_clk();
         }
 
-        if should_send_ack {
-            self.set_sda_low()?;
-        } else {
-            self.set_sda_high()?;
-        }
+        Err(Error::Timeout)
+    }
 
-        self.set_scl_high()?;
-        self.wait_for_clk();
+    /// Send raw bytes between a START and a STOP.
+    ///
+    /// **This bypasses standard addressing.** The first byte is sent as given
+    /// instead of as `(addr << 1) | rw`, for quasi-I2C parts with their own
+    /// command or addressing sequences. The ACK bit is clocked after every
+    /// byte; with `check_acks` a NACK fails with `Err(NoAck)`, otherwise it
+    /// is ignored.
+    pub fn xfer_raw(&mut self, bytes: &[u8], check_acks: bool) -> Result<(), crate::i2c::Error<E>> {
+        self.transaction(|bus| {
+            bus.raw_i2c_start()?;
+            for byte in bytes {
+                bus.i2c_write_byte(*byte)?;
+                if check_acks {
+                    bus.check_ack()?;
+                } else {
+                    bus.i2c_is_ack()?;
+                }
+            }
+            Ok(())
+        })
+    }
 
-        self.set_scl_low()?;
-        self.set_sda_low()?;
-        self.wait_for_clk();
+    /// Write to one address, then read from another after a repeated START.
+    ///
+    /// No STOP is sent between the write and the read, so slaves that reset
+    /// their register pointer on a STOP keep it. [WriteRead] relies on this.
+    ///
+    /// This covers devices where the register pointer is written at a
+    /// different address than the data is read from, such as some muxes and
+    /// PMICs. [WriteRead] is equivalent to this with `waddr == raddr`.
+    ///
+    /// Empty buffers follow the policy in the [module documentation](self):
+    /// with an empty `input` this is a plain write to `waddr`.
+    pub fn write_read_addr(
+        &mut self,
+        waddr: u8,
+        output: &[u8],
+        raddr: u8,
+        input: &mut [u8],
+    ) -> Result<(), crate::i2c::Error<E>> {
+        if input.is_empty() {
+            return self.transaction(|bus| bus.start_write(waddr, output));
+        }
 
-        Ok(byte)
+        self.transaction(|bus| bus.write_read_no_stop(waddr, output, raddr, input))
     }
 
-    fn i2c_write_byte(&mut self, byte: u8) -> Result<(), crate::i2c::Error<E>> {
-        for bit_offset in 0..8 {
-            let out_bit = (byte >> (7 - bit_offset)) & 0b1;
-
-            if out_bit == 1 {
-                self.set_sda_high()?;
-            } else {
-                self.set_sda_low()?;
-            }
+    /// Write to a device and keep the bus, without a STOP.
+    ///
+    /// Sends a START, the address and `output` like [Write], but returns with
+    /// SCL held low instead of sending a STOP, so no other master can take
+    /// the bus and the slave sees one transaction, e.g. for double-buffered
+    /// DAC latches that must be updated together. Continue with
+    /// [continue_write](Self::continue_write) or
+    /// [continue_read](Self::continue_read), which begin with a repeated
+    /// START and end the transaction with a STOP, or with further calls to
+    /// this function. On an error the bus is released as in [Write].
+    pub fn write_no_stop(&mut self, addr: u8, output: &[u8]) -> Result<(), crate::i2c::Error<E>> {
+        self.held(|bus| bus.start_write(addr, output))
+    }
 
-            self.set_scl_high()?;
-            self.wait_for_clk();
+    /// Continue a transaction held by [write_no_stop](Self::write_no_stop)
+    /// with a write, then send the STOP.
+    ///
+    /// The repeated START, address and `output` are sent as in [Write].
+    pub fn continue_write(&mut self, addr: u8, output: &[u8]) -> Result<(), crate::i2c::Error<E>> {
+        self.transaction(|bus| bus.start_write(addr, output))
+    }
 
-            self.set_scl_low()?;
-            self.set_sda_low()?;
-            self.wait_for_clk();
+    /// Continue a transaction held by [write_no_stop](Self::write_no_stop)
+    /// with a read, then send the STOP.
+    ///
+    /// With an empty `input` only the STOP is sent.
+    pub fn continue_read(
+        &mut self,
+        addr: u8,
+        input: &mut [u8],
+    ) -> Result<(), crate::i2c::Error<E>> {
+        if input.is_empty() {
+            return self.stop();
         }
 
-        Ok(())
+        self.transaction(|bus| bus.read_no_stop(addr, input, &mut 0))
     }
 
-    /// Read raw bytes from the slave.
+    /// Read an 8-bit register.
     ///
-    /// **This is a low-level control function.** For normal I2C devices,
-    /// please use the embedded-hal traits [Read], [Write], or
-    /// [WriteRead].
-    #[inline]
-    pub fn raw_read_from_slave(&mut self, input: &mut [u8]) -> Result<(), crate::i2c::Error<E>> {
-        for i in 0..input.len() {
-            let should_send_ack = i != (input.len() - 1);
-            input[i] = self.i2c_read_byte(should_send_ack)?;
-        }
-        Ok(())
+    /// Writes `reg` and reads one byte after a repeated START.
+    pub fn read_reg8(&mut self, addr: u8, reg: u8) -> Result<u8, crate::i2c::Error<E>> {
+        let mut value = [0];
+        self.read_regs8(addr, reg, &mut value)?;
+        Ok(value[0])
     }
 
-    /// Send raw bytes to the slave.
-    ///
-    /// **This is a low-level control function.** For normal I2C devices,
-    /// please use the embedded-hal traits [Read], [Write], or
-    /// [WriteRead].
-    #[inline]
-    pub fn raw_write_to_slave(&mut self, output: &[u8]) -> Result<(), crate::i2c::Error<E>> {
-        for byte in output {
-            self.i2c_write_byte(*byte)?;
-            self.check_ack()?;
-        }
-        Ok(())
+    /// Read `buf.len()` bytes starting at an 8-bit register.
+    pub fn read_regs8(
+        &mut self,
+        addr: u8,
+        reg: u8,
+        buf: &mut [u8],
+    ) -> Result<(), crate::i2c::Error<E>> {
+        self.write_read_addr(addr, &[reg], addr, buf)
     }
 
-    #[inline]
-    fn set_scl_high(&mut self) -> Result<(), crate::i2c::Error<E>> {
-        self.scl.set_high().map_err(Error::Bus)
+    /// Read a register of a device with 16-bit register addresses.
+    ///
+    /// `reg` is sent MSB-first, then one byte is read after a repeated START.
+    pub fn read_reg16(&mut self, addr: u8, reg: u16) -> Result<u8, crate::i2c::Error<E>> {
+        let mut value = [0];
+        self.read_regs16(addr, reg, &mut value)?;
+        Ok(value[0])
     }
 
-    #[inline]
-    fn set_scl_low(&mut self) -> Result<(), crate::i2c::Error<E>> {
-        self.scl.set_low().map_err(Error::Bus)
+    /// Read `buf.len()` bytes starting at a 16-bit register address.
+    pub fn read_regs16(
+        &mut self,
+        addr: u8,
+        reg: u16,
+        buf: &mut [u8],
+    ) -> Result<(), crate::i2c::Error<E>> {
+        self.write_read_addr(addr, &reg.to_be_bytes(), addr, buf)
     }
 
-    #[inline]
-    fn set_sda_high(&mut self) -> Result<(), crate::i2c::Error<E>> {
-        self.sda.set_high().map_err(Error::Bus)
+    /// Write an 8-bit register.
+    pub fn write_reg8(&mut self, addr: u8, reg: u8, value: u8) -> Result<(), crate::i2c::Error<E>> {
+        self.write(addr, &[reg, value])
     }
 
-    #[inline]
-    fn set_sda_low(&mut self) -> Result<(), crate::i2c::Error<E>> {
-        self.sda.set_low().map_err(Error::Bus)
+    /// Write a register of a device with 16-bit register addresses.
+    ///
+    /// `reg` is sent MSB-first, followed by `value`.
+    pub fn write_reg16(
+        &mut self,
+        addr: u8,
+        reg: u16,
+        value: u8,
+    ) -> Result<(), crate::i2c::Error<E>> {
+        let [high, low] = reg.to_be_bytes();
+        self.write(addr, &[high, low, value])
     }
 
-    #[inline]
-    fn wait_for_clk(&mut self) {
-        block!(self.clk.wait()).ok();
+    /// Read from a device, counting the bytes received before any error.
+    ///
+    /// Behaves like [Read], and additionally sets `completed` to the number
+    /// of bytes fully clocked in, also when an error ends the read early.
+    /// This tells a bus glitch at byte 5 apart from a device that does not
+    /// answer at all.
+    pub fn read_counted(
+        &mut self,
+        addr: u8,
+        input: &mut [u8],
+        completed: &mut usize,
+    ) -> Result<(), crate::i2c::Error<E>> {
+        *completed = 0;
+        if input.is_empty() {
+            return Ok(());
+        }
+
+        self.transaction(|bus| bus.read_no_stop(addr, input, completed))
     }
 
-    #[inline]
-    fn check_ack(&mut self) -> Result<(), crate::i2c::Error<E>> {
-        if !self.i2c_is_ack()? {
-            Err(Error::NoAck)
-        } else {
+    /// Read `count` bytes, handing each to `f` as soon as it is received.
+    ///
+    /// Like [Read], but without a buffer, e.g. for dumping a large EEPROM on
+    /// a device with little RAM. All bytes but the last are ACKed and the
+    /// whole stream is framed by a single START and STOP. `f` runs while SCL
+    /// is held low, so it stretches the clock and should be quick.
+    pub fn read_stream<F>(
+        &mut self,
+        addr: u8,
+        count: usize,
+        mut f: F,
+    ) -> Result<(), crate::i2c::Error<E>>
+    where
+        F: FnMut(u8),
+    {
+        if count == 0 {
+            return Ok(());
+        }
+
+        self.transaction(|bus| {
+            // ST
+            bus.raw_i2c_start()?;
+
+            // SAD + R
+            bus.i2c_write_byte((addr << 1) | 0x1)?;
+            bus.check_ack()?;
+
+            for i in 0..count {
+                f(bus.i2c_read_byte(i != count - 1)?);
+            }
             Ok(())
+        })
+    }
+
+    /// Run `op`, retrying up to `retries` times on transient errors.
+    ///
+    /// `NoAck` and `ArbitrationLost` are retried, e.g. for sensors that NACK
+    /// while busy; any other error is returned immediately. After a NACK the
+    /// bus is released with [stop](Self::stop), which covers operations
+    /// built from the raw functions; after a lost arbitration the bus belongs
+    /// to the other master and is left alone. Before each retry the driver
+    /// waits a growing delay of about one byte time per failed attempt. On
+    /// exhaustion the last error is returned. If the STOP after a NACK fails,
+    /// its pin error is returned instead, as the bus state is then unknown.
+    ///
+    /// ```ignore
+    /// let temp = i2c.with_retry(3, |i2c| i2c.read_reg8(0x48, 0x00))?;
+    /// ```
+    pub fn with_retry<T, F>(&mut self, retries: u32, mut op: F) -> Result<T, crate::i2c::Error<E>>
+    where
+        F: FnMut(&mut Self) -> Result<T, crate::i2c::Error<E>>,
+    {
+        let mut attempt = 0;
+        loop {
+            match op(self) {
+                Err(e @ (Error::NoAck | Error::ArbitrationLost)) if attempt < retries => {
+                    if matches!(e, Error::NoAck) {
+                        self.stop()?;
+                    }
+                    attempt += 1;
+                    self.wait_for_clk_ticks(attempt.saturating_mul(RETRY_BACKOFF_TICKS));
+                }
+                result => return result,
+            }
         }
     }
-}
 
-impl<SCL, SDA, CLK, E> Write for I2cBB<SCL, SDA, CLK>
+    /// Issue a STOP condition from whatever state the lines are in.
+    ///
+    /// SCL and SDA are pulled low one after the other before the regular STOP,
+    /// so this releases the bus even after a transaction was abandoned midway,
+    /// e.g. after a NACK. An ongoing non-blocking transaction is discarded.
+    pub fn stop(&mut self) -> Result<(), crate::i2c::Error<E>> {
+        self.transfer = None;
+
+        self.set_scl_low()?;
+        self.wait_for_clk();
+
+        self.set_sda_low()?;
+        self.wait_for_clk();
+
+        self.raw_i2c_stop()
+    }
+
+    /// Write to a device, recording the ACK of every data byte.
+    ///
+    /// Unlike [Write], a NACK on a data byte does not abort the transaction:
+    /// all of `output` is sent and `acks[i]` is set to whether `output[i]` was
+    /// acknowledged, which helps when analysing how a device responds. A
+    /// missing ACK on the address still ends the transaction with a STOP and
+    /// `Err(NoAck)`. `acks` must be as long as `output`, otherwise
+    /// `Err(InvalidData)` is returned without touching the bus.
+    pub fn write_collect_acks(
+        &mut self,
+        addr: u8,
+        output: &[u8],
+        acks: &mut [bool],
+    ) -> Result<(), crate::i2c::Error<E>> {
+        if output.len() != acks.len() {
+            return Err(Error::InvalidData);
+        }
+
+        self.transaction(|bus| {
+            // ST
+            bus.raw_i2c_start()?;
+
+            // SAD + W
+            bus.i2c_write_byte(addr << 1)?;
+            bus.check_ack()?;
+
+            for (byte, ack) in output.iter().zip(acks.iter_mut()) {
+                bus.i2c_write_byte(*byte)?;
+                *ack = bus.i2c_is_ack()?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Non-blocking write.
+    ///
+    /// Each call advances the transaction by at most one clock phase and
+    /// returns `WouldBlock` until the STOP condition has been sent, so the
+    /// transaction can be driven from a cooperative scheduler. The same
+    /// arguments must be passed on every call until `Ok` or an error is
+    /// returned. A NACK is reported after a STOP has released the bus. Any
+    /// other error, such as a failed pin read, releases the bus with
+    /// [stop](Self::stop) before it is returned, except a lost arbitration,
+    /// which leaves the bus to the other master.
+    ///
+    /// The blocking methods do not run on this state machine. They clock
+    /// each byte in a loop, which keeps the many variants, such as ACK
+    /// patterns, held transactions and retries, simple, and spares them
+    /// the dispatch on every clock phase. Both produce the same waveform.
+    pub fn poll_write(&mut self, addr: u8, output: &[u8]) -> nb::Result<(), crate::i2c::Error<E>> {
+        self.poll_transfer(addr << 1, output, &mut [], false)
+    }
+
+    /// Non-blocking read.
+    ///
+    /// See [poll_write](Self::poll_write) for the calling convention.
+    pub fn poll_read(
+        &mut self,
+        addr: u8,
+        input: &mut [u8],
+    ) -> nb::Result<(), crate::i2c::Error<E>> {
+        if input.is_empty() {
+            return Ok(());
+        }
+
+        self.poll_transfer((addr << 1) | 0x1, &[], input, false)
+    }
+
+    /// Non-blocking write followed by a read after a repeated START.
+    ///
+    /// The non-blocking counterpart of [WriteRead], see
+    /// [poll_write](Self::poll_write) for the calling convention. With an
+    /// empty `input` this is a plain write.
+    pub fn poll_write_read(
+        &mut self,
+        addr: u8,
+        output: &[u8],
+        input: &mut [u8],
+    ) -> nb::Result<(), crate::i2c::Error<E>> {
+        if input.is_empty() {
+            return self.poll_write(addr, output);
+        }
+
+        self.poll_transfer(addr << 1, output, input, true)
+    }
+
+    /// Advance a transaction writing `output` to `address`, then reading
+    /// `input`, after a repeated START to the read address if `restart` is
+    /// set.
+    fn poll_transfer(
+        &mut self,
+        address: u8,
+        output: &[u8],
+        input: &mut [u8],
+        restart: bool,
+    ) -> nb::Result<(), crate::i2c::Error<E>> {
+        let mut transfer = self.transfer.take().unwrap_or(Transfer {
+            step: None,
+            ticks_left: 0,
+            byte: 0,
+            nack: false,
+            restarted: false,
+        });
+
+        if transfer.ticks_left > 0 {
+            if let Some(clk) = self.clk.as_mut() {
+                if let Err(nb::Error::WouldBlock) = clk.wait() {
+                    self.transfer = Some(transfer);
+                    return Err(nb::Error::WouldBlock);
+                }
+            }
+            self.tick_count = self.tick_count.wrapping_add(1);
+            transfer.ticks_left -= 1;
+            if transfer.ticks_left > 0 {
+                self.transfer = Some(transfer);
+                return Err(nb::Error::WouldBlock);
+            }
+        }
+
+        let next = match transfer.step {
+            None => Some(Step::Start(0)),
+            // release the bus before reporting a NACK
+            Some(step) if transfer.nack && !matches!(step, Step::Stop(_)) => Some(Step::Stop(0)),
+            // after the repeated START only the read address is written
+            Some(step) if transfer.restarted => Self::next_step(step, 0, input.len(), false),
+            Some(step) => Self::next_step(step, output.len(), input.len(), restart),
+        };
+
+        match next {
+            Some(step) => {
+                if matches!(step, Step::Start(0)) && transfer.step.is_some() {
+                    transfer.restarted = true;
+                }
+                transfer.ticks_left =
+                    match self.enter_step(step, &mut transfer, address, output, input) {
+                        Ok(ticks) => ticks,
+                        // the bus belongs to the winning master now
+                        Err(Error::ArbitrationLost) => {
+                            return Err(nb::Error::Other(Error::ArbitrationLost))
+                        }
+                        Err(e) => {
+                            self.stop().ok();
+                            return Err(nb::Error::Other(e));
+                        }
+                    };
+                transfer.step = Some(step);
+                self.transfer = Some(transfer);
+                Err(nb::Error::WouldBlock)
+            }
+            None if transfer.nack => Err(nb::Error::Other(Error::NoAck)),
+            None => Ok(()),
+        }
+    }
+
+    fn next_step(step: Step, output_len: usize, input_len: usize, restart: bool) -> Option<Step> {
+        match step {
+            Step::Start(phase) if phase < 2 => Some(Step::Start(phase + 1)),
+            Step::Start(_) => Some(Step::WriteBit {
+                index: 0,
+                bit: 0,
+                high: true,
+            }),
+            Step::WriteBit { index, bit, high } if high || bit < 8 => Some(Step::WriteBit {
+                index,
+                bit: if high { bit } else { bit + 1 },
+                high: !high,
+            }),
+            Step::WriteBit { index, .. } if index < output_len => Some(Step::WriteBit {
+                index: index + 1,
+                bit: 0,
+                high: true,
+            }),
+            Step::WriteBit { .. } if restart => Some(Step::Start(0)),
+            Step::WriteBit { .. } if input_len > 0 => Some(Step::ReadBit {
+                index: 0,
+                bit: 0,
+                high: true,
+            }),
+            Step::WriteBit { .. } => Some(Step::Stop(0)),
+            Step::ReadBit { index, bit, high } if high || bit < 8 => Some(Step::ReadBit {
+                index,
+                bit: if high { bit } else { bit + 1 },
+                high: !high,
+            }),
+            Step::ReadBit { index, .. } if index + 1 < input_len => Some(Step::ReadBit {
+                index: index + 1,
+                bit: 0,
+                high: true,
+            }),
+            Step::ReadBit { .. } => Some(Step::Stop(0)),
+            Step::Stop(0) => Some(Step::Stop(1)),
+            Step::Stop(_) => None,
+        }
+    }
+
+    /// Drive the lines for `step` and return the number of ticks to wait.
+    fn enter_step(
+        &mut self,
+        step: Step,
+        transfer: &mut Transfer,
+        address: u8,
+        output: &[u8],
+        input: &mut [u8],
+    ) -> Result<u32, crate::i2c::Error<E>> {
+        match step {
+            Step::Start(0) => {
+                self.set_sda_high()?;
+                self.set_scl_high()?;
+            }
+            Step::Start(1) => {
+                self.set_sda_low()?;
+                return Ok(self.start_hold_ticks);
+            }
+            Step::Start(_) => self.set_scl_low()?,
+            Step::WriteBit {
+                bit: 8, high: true, ..
+            } => {
+                self.set_sda_high()?;
+                self.set_scl_high()?;
+                return Ok(1 + self.rise_ticks);
+            }
+            Step::WriteBit { bit: 8, .. } => {
+                let ack = !self.sample_sda()?;
+                self.set_scl_low()?;
+                self.set_sda_low()?;
+                transfer.nack = !ack;
+                return Ok(1 + self.inter_byte_ticks);
+            }
+            Step::WriteBit {
+                index,
+                bit,
+                high: true,
+            } => {
+                let value = if index == 0 {
+                    address | transfer.restarted as u8
+                } else {
+                    output[index - 1]
+                };
+                if (value >> self.bit_shift(bit)) & 0b1 == 1 {
+                    self.set_sda_high()?;
+                } else {
+                    self.set_sda_low()?;
+                }
+                self.set_scl_high()?;
+            }
+            Step::WriteBit { index, bit, .. } => {
+                if self.check_arbitration {
+                    let value = if index == 0 {
+                        address | transfer.restarted as u8
+                    } else {
+                        output[index - 1]
+                    };
+                    self.check_sda_released((value >> self.bit_shift(bit)) & 0b1 == 1)?;
+                }
+                self.set_scl_low()?;
+                self.set_sda_low()?;
+            }
+            Step::ReadBit {
+                index,
+                bit: 8,
+                high: true,
+            } => {
+                input[index] = transfer.byte;
+                transfer.byte = 0;
+                if index + 1 < input.len() {
+                    self.set_sda_low()?;
+                } else {
+                    self.set_sda_high()?;
+                }
+                self.set_scl_high()?;
+            }
+            Step::ReadBit { bit: 8, .. } => {
+                self.set_scl_low()?;
+                self.set_sda_low()?;
+                self.partial_bits = 0;
+                return Ok(1 + self.inter_byte_ticks);
+            }
+            Step::ReadBit {
+                bit, high: true, ..
+            } => {
+                if bit == 0 {
+                    self.partial_bits = 0;
+                    self.set_sda_high()?;
+                }
+                self.set_scl_high()?;
+                return Ok(1 + self.rise_ticks);
+            }
+            Step::ReadBit { bit, .. } => {
+                if self.sample_sda()? {
+                    transfer.byte |= 1 << self.bit_shift(bit);
+                }
+                self.partial_byte = transfer.byte;
+                self.partial_bits = bit + 1;
+                self.set_scl_low()?;
+            }
+            Step::Stop(0) => {
+                self.set_scl_high()?;
+                return Ok(self.stop_hold_ticks);
+            }
+            Step::Stop(_) => self.set_sda_high()?,
+        }
+
+        Ok(1)
+    }
+
+    /// Probe the bus lines for diagnostics.
+    ///
+    /// Returns `(scl_level, sda_level)`. SCL is output-only, so its level is
+    /// the one last driven by this driver (high before the first
+    /// transaction); SDA is read from the pin. If SCL can also be read, use
+    /// [read_line_state](Self::read_line_state) to sample both lines.
+    pub fn line_state(&mut self) -> Result<(bool, bool), crate::i2c::Error<E>> {
+        let sda_level = self.sda.is_high().map_err(Error::Bus)?;
+        Ok((self.scl_level, sda_level))
+    }
+
+    /// Release a slave holding SDA low.
+    ///
+    /// A slave interrupted in the middle of sending a byte, e.g. by a master
+    /// reset, holds SDA low until it has clocked out the rest of the byte.
+    /// With SDA released, SCL is pulsed up to nine times until SDA reads
+    /// high, and the bus is then left idle with a STOP. Returns whether SDA
+    /// was released.
+    pub fn recover_bus(&mut self) -> Result<bool, crate::i2c::Error<E>> {
+        self.set_sda_high()?;
+        for _ in 0..9 {
+            if self.sda.is_high().map_err(Error::Bus)? {
+                break;
+            }
+            self.set_scl_low()?;
+            self.wait_for_clk();
+            self.set_scl_high()?;
+            self.wait_for_clk();
+        }
+
+        let released = self.sda.is_high().map_err(Error::Bus)?;
+        if released {
+            self.stop()?;
+        }
+        Ok(released)
+    }
+
+    /// Run a non-destructive bus self-test.
+    ///
+    /// Checks that both lines are high at idle, runs
+    /// [recover_bus](Self::recover_bus) if SDA is stuck low, and sends a
+    /// START directly followed by a STOP, which slaves ignore, checking that
+    /// SDA follows. SCL is read back if clock stretching is enabled with
+    /// [set_clock_stretch_timeout](Self::set_clock_stretch_timeout),
+    /// otherwise its driven level is reported. Only GPIO errors are returned
+    /// as errors; a stretch timeout fails the START/STOP check.
+    pub fn bus_health(&mut self) -> Result<BusHealth, crate::i2c::Error<E>> {
+        let scl_idle_high = match self.scl_released {
+            Some(scl_released) => scl_released(&self.scl).map_err(Error::Bus)?,
+            None => self.scl_level,
+        };
+        let sda_idle_high = self.sda.is_high().map_err(Error::Bus)?;
+
+        let recovery_needed = !sda_idle_high;
+        let recovered = recovery_needed && self.recover_bus()?;
+
+        let start_stop_ok = match self.start_stop_check() {
+            Err(Error::Timeout) => {
+                self.stop().ok();
+                false
+            }
+            result => result?,
+        };
+
+        Ok(BusHealth {
+            scl_idle_high,
+            sda_idle_high,
+            recovery_needed,
+            recovered,
+            start_stop_ok,
+        })
+    }
+
+    /// START and STOP, checking SDA after each
+    fn start_stop_check(&mut self) -> Result<bool, crate::i2c::Error<E>> {
+        self.raw_i2c_start()?;
+        let sda_went_low = self.sda.is_low().map_err(Error::Bus)?;
+        self.raw_i2c_stop()?;
+        let sda_released = self.sda.is_high().map_err(Error::Bus)?;
+        Ok(sda_went_low && sda_released)
+    }
+
+    fn i2c_is_ack(&mut self) -> Result<bool, crate::i2c::Error<E>> {
+        self.set_sda_high()?;
+        self.set_scl_high()?;
+        self.wait_for_clk();
+        self.wait_for_clk_ticks(self.rise_ticks);
+
+        let ack = !self.sample_sda()?;
+
+        self.set_scl_low()?;
+        self.set_sda_low()?;
+        self.wait_for_clk();
+        self.wait_for_clk_ticks(self.inter_byte_ticks);
+
+        Ok(ack)
+    }
+
+    fn i2c_read_byte(&mut self, should_send_ack: bool) -> Result<u8, crate::i2c::Error<E>> {
+        self.check_abort()?;
+        let mut byte: u8 = 0;
+        self.partial_bits = 0;
+
+        self.set_sda_high()?;
+
+        for bit_offset in 0..8 {
+            self.set_scl_high()?;
+            self.wait_for_clk();
+            self.wait_for_clk_ticks(self.rise_ticks);
+
+            if self.sample_sda()? {
+                byte |= 1 << self.bit_shift(bit_offset);
+            }
+            self.partial_byte = byte;
+            self.partial_bits = bit_offset + 1;
+
+            self.set_scl_low()?;
+            self.wait_for_clk();
+        }
+
+        if should_send_ack {
+            self.set_sda_low()?;
+        } else {
+            self.set_sda_high()?;
+        }
+
+        self.set_scl_high()?;
+        self.wait_for_clk();
+
+        self.set_scl_low()?;
+        self.set_sda_low()?;
+        self.partial_bits = 0;
+        self.wait_for_clk();
+        self.wait_for_clk_ticks(self.inter_byte_ticks);
+
+        Ok(byte)
+    }
+
+    fn i2c_write_byte(&mut self, byte: u8) -> Result<(), crate::i2c::Error<E>> {
+        self.check_abort()?;
+        for bit_offset in 0..8 {
+            let out_bit = (byte >> self.bit_shift(bit_offset)) & 0b1;
+
+            if out_bit == 1 {
+                self.set_sda_high()?;
+            } else {
+                self.set_sda_low()?;
+            }
+
+            self.set_scl_high()?;
+            self.wait_for_clk();
+
+            if self.check_arbitration {
+                self.check_sda_released(out_bit == 1)?;
+            }
+
+            self.set_scl_low()?;
+            self.set_sda_low()?;
+            self.wait_for_clk();
+        }
+
+        Ok(())
+    }
+
+    /// Read SDA by majority vote over `sample_count` reads
+    #[inline]
+    fn sample_sda(&self) -> Result<bool, crate::i2c::Error<E>> {
+        let mut high = 0;
+        for _ in 0..self.sample_count {
+            if self.sda.is_high().map_err(Error::Bus)? {
+                high += 1;
+            }
+        }
+        Ok(high * 2 > self.sample_count)
+    }
+
+    #[inline]
+    fn check_abort(&self) -> Result<(), crate::i2c::Error<E>> {
+        match self.abort {
+            Some(flag) if flag.load(Ordering::Relaxed) => Err(Error::Aborted),
+            _ => Ok(()),
+        }
+    }
+
+    /// Detect another master driving SDA low while this one sends a 1
+    ///
+    /// Called with SCL high. The lines are left released on arbitration loss.
+    #[inline]
+    fn check_sda_released(&mut self, sent_high: bool) -> Result<(), crate::i2c::Error<E>> {
+        if sent_high && self.sda.is_low().map_err(Error::Bus)? {
+            return Err(Error::ArbitrationLost);
+        }
+        Ok(())
+    }
+
+    /// Read raw bytes from the slave.
+    ///
+    /// **This is a low-level control function.** For normal I2C devices,
+    /// please use the embedded-hal traits [Read], [Write], or
+    /// [WriteRead].
+    #[inline]
+    pub fn raw_read_from_slave(&mut self, input: &mut [u8]) -> Result<(), crate::i2c::Error<E>> {
+        self.read_from_slave_counted(input, &mut 0)
+    }
+
+    /// Read raw bytes from the slave, ACKing according to `ack_pattern`.
+    ///
+    /// Byte `i` is ACKed if `ack_pattern[i % ack_pattern.len()]` is `true`
+    /// and NACKed otherwise, for streaming converters that expect e.g. two
+    /// ACKs and a NACK per sample. An empty pattern ACKs all bytes but the
+    /// last, like [raw_read_from_slave](Self::raw_read_from_slave).
+    ///
+    /// **This is a low-level control function.** For normal I2C devices,
+    /// please use the embedded-hal traits [Read], [Write], or
+    /// [WriteRead].
+    pub fn read_pattern(
+        &mut self,
+        buf: &mut [u8],
+        ack_pattern: &[bool],
+    ) -> Result<(), crate::i2c::Error<E>> {
+        if ack_pattern.is_empty() {
+            return self.raw_read_from_slave(buf);
+        }
+
+        self.read_with_acks(buf, |i| ack_pattern[i % ack_pattern.len()])
+    }
+
+    /// Read raw bytes from the slave, ACKing byte `i` if `ack(i)` is `true`.
+    ///
+    /// Covers any ACK scheme, e.g. `|i| i != 0` for devices that expect the
+    /// first byte to be NACKed to select a mode. `ack` is called once per
+    /// byte, in order, before the byte is clocked in.
+    ///
+    /// **This is a low-level control function.** For normal I2C devices,
+    /// please use the embedded-hal traits [Read], [Write], or
+    /// [WriteRead].
+    pub fn read_with_acks<F>(
+        &mut self,
+        buf: &mut [u8],
+        mut ack: F,
+    ) -> Result<(), crate::i2c::Error<E>>
+    where
+        F: FnMut(usize) -> bool,
+    {
+        for (i, byte) in buf.iter_mut().enumerate() {
+            *byte = self.i2c_read_byte(ack(i))?;
+        }
+        Ok(())
+    }
+
+    /// Read raw bytes from the slave, counting the bytes completed in
+    /// `completed`, which stays valid if an error interrupts the read.
+    fn read_from_slave_counted(
+        &mut self,
+        input: &mut [u8],
+        completed: &mut usize,
+    ) -> Result<(), crate::i2c::Error<E>> {
+        for i in 0..input.len() {
+            let should_send_ack = i != (input.len() - 1);
+            input[i] = self.i2c_read_byte(should_send_ack)?;
+            *completed = i + 1;
+        }
+        Ok(())
+    }
+
+    /// Send raw bytes to the slave.
+    ///
+    /// **This is a low-level control function.** For normal I2C devices,
+    /// please use the embedded-hal traits [Read], [Write], or
+    /// [WriteRead].
+    #[inline]
+    pub fn raw_write_to_slave(&mut self, output: &[u8]) -> Result<(), crate::i2c::Error<E>> {
+        for byte in output {
+            self.i2c_write_byte(*byte)?;
+            self.check_ack()?;
+        }
+        Ok(())
+    }
+
+    /// Shift of bit `index` of a byte, counting in transmission order
+    #[inline]
+    fn bit_shift(&self, index: u8) -> u8 {
+        match self.bit_order {
+            BitOrder::MSBFirst => 7 - index,
+            BitOrder::LSBFirst => index,
+        }
+    }
+
+    #[inline]
+    fn set_scl_high(&mut self) -> Result<(), crate::i2c::Error<E>> {
+        self.scl.set_high().map_err(Error::Bus)?;
+        self.scl_level = true;
+        self.wait_for_clk_ticks(self.settle_ticks);
+
+        if let Some(scl_released) = self.scl_released {
+            let mut ticks = 0;
+            while !scl_released(&self.scl).map_err(Error::Bus)? {
+                if ticks == self.stretch_timeout_ticks {
+                    return Err(Error::Timeout);
+                }
+                self.wait_for_clk();
+                ticks += 1;
+            }
+        }
+
+        Ok(())
+    }
+
+    #[inline]
+    fn set_scl_low(&mut self) -> Result<(), crate::i2c::Error<E>> {
+        self.scl.set_low().map_err(Error::Bus)?;
+        self.scl_level = false;
+        Ok(())
+    }
+
+    #[inline]
+    fn set_sda_high(&mut self) -> Result<(), crate::i2c::Error<E>> {
+        self.sda.set_high().map_err(Error::Bus)?;
+        self.wait_for_clk_ticks(self.settle_ticks);
+        Ok(())
+    }
+
+    #[inline]
+    fn set_sda_low(&mut self) -> Result<(), crate::i2c::Error<E>> {
+        self.sda.set_low().map_err(Error::Bus)
+    }
+
+    #[inline]
+    fn wait_for_clk(&mut self) {
+        self.tick_count = self.tick_count.wrapping_add(1);
+        if let Some(clk) = self.clk.as_mut() {
+            half_period_wait(clk);
+        }
+    }
+
+    #[inline]
+    fn wait_for_clk_ticks(&mut self, ticks: u32) {
+        for _ in 0..ticks {
+            self.wait_for_clk();
+        }
+    }
+
+    /// START, address and `output`, without a STOP
+    fn start_write(&mut self, addr: u8, output: &[u8]) -> Result<(), crate::i2c::Error<E>> {
+        // ST
+        self.raw_i2c_start()?;
+
+        // SAD + W
+        self.i2c_write_byte(addr << 1)?;
+        self.check_ack()?;
+
+        self.raw_write_to_slave(output)
+    }
+
+    fn read_no_stop(
+        &mut self,
+        addr: u8,
+        input: &mut [u8],
+        completed: &mut usize,
+    ) -> Result<(), crate::i2c::Error<E>> {
+        // ST
+        self.raw_i2c_start()?;
+
+        // SAD + R
+        self.i2c_write_byte((addr << 1) | 0x1)?;
+        self.check_ack()?;
+
+        self.read_from_slave_counted(input, completed)
+    }
+
+    fn write_read_no_stop(
+        &mut self,
+        waddr: u8,
+        output: &[u8],
+        raddr: u8,
+        input: &mut [u8],
+    ) -> Result<(), crate::i2c::Error<E>> {
+        self.start_write(waddr, output)?;
+
+        // SR
+        self.read_no_stop(raddr, input, &mut 0)
+    }
+
+    /// Run `f`, releasing the bus only if it fails.
+    ///
+    /// Errors are handled as in [transaction](Self::transaction), but after a
+    /// successful `f` the bus is kept without a STOP.
+    fn held<T, F>(&mut self, f: F) -> Result<T, crate::i2c::Error<E>>
+    where
+        F: FnOnce(&mut Self) -> Result<T, crate::i2c::Error<E>>,
+    {
+        match f(self) {
+            Ok(value) => Ok(value),
+            // the bus belongs to the winning master now
+            Err(Error::ArbitrationLost) => Err(Error::ArbitrationLost),
+            Err(e) => {
+                self.stop().ok();
+                Err(e)
+            }
+        }
+    }
+
+    /// Run `f` and always release the bus afterwards.
+    ///
+    /// A STOP follows a successful `f`. If `f` fails, the bus is released
+    /// with [stop](Self::stop) from whatever state it was left in and the
+    /// original error is returned; an error during that STOP is dropped in
+    /// its favour.
+    fn transaction<T, F>(&mut self, f: F) -> Result<T, crate::i2c::Error<E>>
+    where
+        F: FnOnce(&mut Self) -> Result<T, crate::i2c::Error<E>>,
+    {
+        match f(self) {
+            Ok(value) => {
+                // SP
+                self.raw_i2c_stop()?;
+                Ok(value)
+            }
+            // the bus belongs to the winning master now
+            Err(Error::ArbitrationLost) => Err(Error::ArbitrationLost),
+            Err(e) => {
+                self.stop().ok();
+                Err(e)
+            }
+        }
+    }
+
+    #[inline]
+    fn check_ack(&mut self) -> Result<(), crate::i2c::Error<E>> {
+        if !self.i2c_is_ack()? {
+            Err(Error::NoAck)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl<SCL, P, CLK, E> I2cBB<SCL, Tristate<P>, CLK>
 where
     SCL: OutputPin<Error = E>,
+    P: TristatePin<Error = E> + InputPin<Error = E>,
+    CLK: CountDown + Periodic,
+{
+    /// Create instance with SDA emulating an open-drain output
+    ///
+    /// For MCUs without open-drain outputs: SDA is released by switching it to
+    /// input and pulled low by switching it to output low, see [Tristate].
+    pub fn new_tristate(scl: SCL, sda: P, clk: CLK) -> Self {
+        Self::new(scl, Tristate::new(sda), clk)
+    }
+}
+
+impl<SCL, SDA, D, E> I2cBB<SCL, SDA, DelayTimer<D>>
+where
+    SCL: OutputPin<Error = E>,
+    SDA: OutputPin<Error = E> + InputPin<Error = E>,
+    D: DelayUs<u32>,
+{
+    /// Create instance clocked by a blocking delay
+    ///
+    /// `half_period_us` is the duration of one clock phase in microseconds,
+    /// i.e. half of the I2C clock period. See [DelayTimer].
+    pub fn new_with_delay(scl: SCL, sda: SDA, delay: D, half_period_us: u32) -> Self {
+        let mut i2c = Self::new(scl, sda, DelayTimer::new(delay, half_period_us));
+        i2c.configured_hz = 1_000_000u32.checked_div(half_period_us).unwrap_or(0);
+        i2c
+    }
+}
+
+impl<SCL, SDA, CLK, E> I2cBB<SCL, SDA, CLK>
+where
+    SCL: OutputPin<Error = E> + InputPin<Error = E>,
     SDA: OutputPin<Error = E> + InputPin<Error = E>,
     CLK: CountDown + Periodic,
 {
-    type Error = crate::i2c::Error<E>;
+    /// Sample both bus lines for diagnostics.
+    ///
+    /// Requires an SCL pin that can also be read, e.g. an open-drain output
+    /// with an input buffer. Returns `(scl_level, sda_level)` as seen on the
+    /// bus, which reveals a slave holding either line low.
+    pub fn read_line_state(&mut self) -> Result<(bool, bool), crate::i2c::Error<E>> {
+        let scl_level = self.scl.is_high().map_err(Error::Bus)?;
+        let sda_level = self.sda.is_high().map_err(Error::Bus)?;
+        Ok((scl_level, sda_level))
+    }
 
-    fn write(&mut self, addr: u8, output: &[u8]) -> Result<(), Self::Error> {
-        // ST
-        self.raw_i2c_start()?;
+    /// Tolerate slaves stretching the clock.
+    ///
+    /// With `Some(max_ticks)`, every time SCL is released it is read back,
+    /// and while a slave holds it low the driver waits up to `max_ticks`
+    /// timer ticks for it to rise before continuing. If it does not rise in
+    /// time, the transaction fails with `Err(Timeout)`. `None` (the default)
+    /// disables the check. The non-blocking API blocks while the clock is
+    /// stretched.
+    pub fn set_clock_stretch_timeout(&mut self, max_ticks: Option<u32>) {
+        match max_ticks {
+            Some(ticks) => {
+                self.scl_released = Some(|scl| scl.is_high());
+                self.stretch_timeout_ticks = ticks;
+            }
+            None => self.scl_released = None,
+        }
+    }
 
-        // SAD + W
-        self.i2c_write_byte((addr << 1) | 0x0)?;
-        self.check_ack()?;
+    /// Detect losing arbitration to another master.
+    ///
+    /// When enabled, SDA is read back while SCL is high after every 1 bit
+    /// written, including the address. If another master pulls it low, the
+    /// transaction stops with `Err(ArbitrationLost)`, leaving both lines
+    /// released and sending no STOP. Disabled by default.
+    ///
+    /// Masters synchronize their clocks through SCL, so this should be
+    /// combined with [set_clock_stretch_timeout](Self::set_clock_stretch_timeout).
+    pub fn set_arbitration_check(&mut self, enabled: bool) {
+        self.check_arbitration = enabled;
+    }
+}
 
-        self.raw_write_to_slave(output)?;
+/// Builder for [I2cBB] with named setters
+///
+/// SCL, SDA and the timer are required; [build](I2cBuilder::build) is only
+/// available once all of them have been set, so a missing pin is a compile
+/// error. The timing options default to the values used by [I2cBB::new].
+///
+/// ```ignore
+/// let i2c = I2cBuilder::new()
+///     .scl(scl)
+///     .sda(sda)
+///     .clk(timer)
+///     .timing_preset(TimingPreset::Fast)
+///     .build();
+/// ```
+pub struct I2cBuilder<SCL = (), SDA = (), CLK = ()> {
+    scl: SCL,
+    sda: SDA,
+    clk: CLK,
+    start_hold_ticks: u32,
+    stop_hold_ticks: u32,
+    rise_ticks: u32,
+    bit_order: BitOrder,
+    sample_count: u8,
+    settle_ticks: u32,
+}
 
-        // SP
-        self.raw_i2c_stop()
+impl I2cBuilder {
+    /// Create a builder without pins and timer
+    pub fn new() -> Self {
+        I2cBuilder {
+            scl: (),
+            sda: (),
+            clk: (),
+            start_hold_ticks: 1,
+            stop_hold_ticks: 1,
+            rise_ticks: 0,
+            bit_order: BitOrder::default(),
+            sample_count: 1,
+            settle_ticks: 0,
+        }
     }
 }
 
-impl<SCL, SDA, CLK, E> Read for I2cBB<SCL, SDA, CLK>
+impl Default for I2cBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<SCL, SDA, CLK> I2cBuilder<SCL, SDA, CLK> {
+    /// Set the SCL pin
+    pub fn scl<P>(self, scl: P) -> I2cBuilder<P, SDA, CLK> {
+        I2cBuilder {
+            scl,
+            sda: self.sda,
+            clk: self.clk,
+            start_hold_ticks: self.start_hold_ticks,
+            stop_hold_ticks: self.stop_hold_ticks,
+            rise_ticks: self.rise_ticks,
+            bit_order: self.bit_order,
+            sample_count: self.sample_count,
+            settle_ticks: self.settle_ticks,
+        }
+    }
+
+    /// Set the SDA pin
+    pub fn sda<P>(self, sda: P) -> I2cBuilder<SCL, P, CLK> {
+        I2cBuilder {
+            scl: self.scl,
+            sda,
+            clk: self.clk,
+            start_hold_ticks: self.start_hold_ticks,
+            stop_hold_ticks: self.stop_hold_ticks,
+            rise_ticks: self.rise_ticks,
+            bit_order: self.bit_order,
+            sample_count: self.sample_count,
+            settle_ticks: self.settle_ticks,
+        }
+    }
+
+    /// Set the timer
+    pub fn clk<T>(self, clk: T) -> I2cBuilder<SCL, SDA, T> {
+        I2cBuilder {
+            scl: self.scl,
+            sda: self.sda,
+            clk,
+            start_hold_ticks: self.start_hold_ticks,
+            stop_hold_ticks: self.stop_hold_ticks,
+            rise_ticks: self.rise_ticks,
+            bit_order: self.bit_order,
+            sample_count: self.sample_count,
+            settle_ticks: self.settle_ticks,
+        }
+    }
+
+    /// Set the START and STOP hold ticks, see [I2cBB::new_with_hold_ticks]
+    pub fn hold_ticks(mut self, start_hold_ticks: u32, stop_hold_ticks: u32) -> Self {
+        self.start_hold_ticks = start_hold_ticks;
+        self.stop_hold_ticks = stop_hold_ticks;
+        self
+    }
+
+    /// Set the SDA rise time allowance, see [I2cBB::set_rise_ticks]
+    pub fn rise_ticks(mut self, ticks: u32) -> Self {
+        self.rise_ticks = ticks;
+        self
+    }
+
+    /// Set the bit order, see [I2cBB::set_bit_order]
+    pub fn bit_order(mut self, order: BitOrder) -> Self {
+        self.bit_order = order;
+        self
+    }
+
+    /// Set the number of SDA reads per bit, see [I2cBB::set_sample_count]
+    pub fn sample_count(mut self, count: u8) -> Self {
+        self.sample_count = count;
+        self
+    }
+
+    /// Apply a bus speed timing preset, see [I2cBB::set_timing_preset]
+    ///
+    /// Overrides the hold, rise and settle ticks set so far.
+    pub fn timing_preset(mut self, preset: TimingPreset) -> Self {
+        let (hold_ticks, rise_ticks, settle_ticks) = preset.ticks();
+        self.start_hold_ticks = hold_ticks;
+        self.stop_hold_ticks = hold_ticks;
+        self.rise_ticks = rise_ticks;
+        self.settle_ticks = settle_ticks;
+        self
+    }
+
+    /// Set the line release settle ticks, see [I2cBB::set_settle_ticks]
+    pub fn settle_ticks(mut self, ticks: u32) -> Self {
+        self.settle_ticks = ticks;
+        self
+    }
+}
+
+impl<SCL, SDA, CLK, E> I2cBuilder<SCL, SDA, CLK>
 where
     SCL: OutputPin<Error = E>,
     SDA: OutputPin<Error = E> + InputPin<Error = E>,
     CLK: CountDown + Periodic,
 {
-    type Error = crate::i2c::Error<E>;
+    /// Create the [I2cBB] instance
+    pub fn build(self) -> I2cBB<SCL, SDA, CLK> {
+        let mut i2c = I2cBB::new_with_hold_ticks(
+            self.scl,
+            self.sda,
+            self.clk,
+            self.start_hold_ticks,
+            self.stop_hold_ticks,
+        );
+        i2c.set_rise_ticks(self.rise_ticks);
+        i2c.set_bit_order(self.bit_order);
+        i2c.set_sample_count(self.sample_count);
+        i2c.set_settle_ticks(self.settle_ticks);
+        i2c
+    }
+}
 
-    fn read(&mut self, addr: u8, input: &mut [u8]) -> Result<(), Self::Error> {
-        if input.is_empty() {
-            return Ok(());
-        }
+/// Shows the timing and protocol configuration; pins and timer are omitted
+impl<SCL, SDA, CLK> core::fmt::Debug for I2cBB<SCL, SDA, CLK>
+where
+    SCL: OutputPin,
+    SDA: OutputPin + InputPin,
+    CLK: CountDown + Periodic,
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let stretch_timeout_ticks = self.scl_released.map(|_| self.stretch_timeout_ticks);
+        f.debug_struct("I2cBB")
+            .field("start_hold_ticks", &self.start_hold_ticks)
+            .field("stop_hold_ticks", &self.stop_hold_ticks)
+            .field("rise_ticks", &self.rise_ticks)
+            .field("settle_ticks", &self.settle_ticks)
+            .field("inter_byte_ticks", &self.inter_byte_ticks)
+            .field("bit_order", &self.bit_order)
+            .field("stretch_timeout_ticks", &stretch_timeout_ticks)
+            .field("check_arbitration", &self.check_arbitration)
+            .field("sample_count", &self.sample_count)
+            .field("configured_hz", &self.configured_hz)
+            .finish_non_exhaustive()
+    }
+}
 
-        // ST
-        self.raw_i2c_start()?;
+impl<SCL, SDA, CLK, E> Write for I2cBB<SCL, SDA, CLK>
+where
+    SCL: OutputPin<Error = E>,
+    SDA: OutputPin<Error = E> + InputPin<Error = E>,
+    CLK: CountDown + Periodic,
+{
+    type Error = crate::i2c::Error<E>;
 
-        // SAD + R
-        self.i2c_write_byte((addr << 1) | 0x1)?;
-        self.check_ack()?;
+    fn write(&mut self, addr: u8, output: &[u8]) -> Result<(), Self::Error> {
+        self.transaction(|bus| bus.start_write(addr, output))
+    }
+}
 
-        self.raw_read_from_slave(input)?;
+impl<SCL, SDA, CLK, E> Read for I2cBB<SCL, SDA, CLK>
+where
+    SCL: OutputPin<Error = E>,
+    SDA: OutputPin<Error = E> + InputPin<Error = E>,
+    CLK: CountDown + Periodic,
+{
+    type Error = crate::i2c::Error<E>;
 
-        // SP
-        self.raw_i2c_stop()
+    fn read(&mut self, addr: u8, input: &mut [u8]) -> Result<(), Self::Error> {
+        self.read_counted(addr, input, &mut 0)
     }
 }
 
@@ -313,29 +1920,790 @@ where
     type Error = crate::i2c::Error<E>;
 
     fn write_read(&mut self, addr: u8, output: &[u8], input: &mut [u8]) -> Result<(), Self::Error> {
-        if output.is_empty() || input.is_empty() {
-            return Err(Error::InvalidData);
+        self.write_read_addr(addr, output, addr, input)
+    }
+}
+
+/// Multiplexer channel outside 0 to 7, rejected by [MuxedI2c]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct InvalidChannel(pub u8);
+
+/// Access to one channel behind a TCA9548-style I2C multiplexer
+///
+/// Every [Write], [Read] and [WriteRead] first writes the channel select
+/// byte `1 << channel` to the multiplexer at `mux_addr`, then performs the
+/// access on the inner bus, so a device is never addressed while another
+/// channel is still selected. The selection is sent as a separate
+/// transaction before each access.
+pub struct MuxedI2c<'a, I2C> {
+    i2c: &'a mut I2C,
+    mux_addr: u8,
+    channel: u8,
+}
+
+impl<'a, I2C, E> MuxedI2c<'a, I2C>
+where
+    I2C: Write<Error = E>,
+{
+    /// Create instance for `channel` (0 to 7) of the multiplexer at
+    /// `mux_addr`
+    ///
+    /// Returns `Err(InvalidChannel)` for a channel above 7.
+    pub fn new(i2c: &'a mut I2C, mux_addr: u8, channel: u8) -> Result<Self, InvalidChannel> {
+        Self::check_channel(channel)?;
+        Ok(MuxedI2c {
+            i2c,
+            mux_addr,
+            channel,
+        })
+    }
+
+    /// Switch to another channel for subsequent accesses
+    ///
+    /// A channel above 7 is rejected with `Err(InvalidChannel)`, keeping the
+    /// current one.
+    pub fn set_channel(&mut self, channel: u8) -> Result<(), InvalidChannel> {
+        Self::check_channel(channel)?;
+        self.channel = channel;
+        Ok(())
+    }
+
+    /// Channel selected before each access
+    pub fn channel(&self) -> u8 {
+        self.channel
+    }
+
+    fn check_channel(channel: u8) -> Result<(), InvalidChannel> {
+        if channel > 7 {
+            return Err(InvalidChannel(channel));
         }
+        Ok(())
+    }
 
-        // ST
-        self.raw_i2c_start()?;
+    fn select(&mut self) -> Result<(), E> {
+        self.i2c.write(self.mux_addr, &[1 << self.channel])
+    }
+}
 
-        // SAD + W
-        self.i2c_write_byte((addr << 1) | 0x0)?;
-        self.check_ack()?;
+impl<I2C, E> Write for MuxedI2c<'_, I2C>
+where
+    I2C: Write<Error = E>,
+{
+    type Error = E;
 
-        self.raw_write_to_slave(output)?;
+    fn write(&mut self, addr: u8, output: &[u8]) -> Result<(), E> {
+        self.select()?;
+        self.i2c.write(addr, output)
+    }
+}
 
-        // SR
-        self.raw_i2c_start()?;
+impl<I2C, E> Read for MuxedI2c<'_, I2C>
+where
+    I2C: Write<Error = E> + Read<Error = E>,
+{
+    type Error = E;
 
-        // SAD + R
-        self.i2c_write_byte((addr << 1) | 0x1)?;
-        self.check_ack()?;
+    fn read(&mut self, addr: u8, input: &mut [u8]) -> Result<(), E> {
+        self.select()?;
+        self.i2c.read(addr, input)
+    }
+}
 
-        self.raw_read_from_slave(input)?;
+impl<I2C, E> WriteRead for MuxedI2c<'_, I2C>
+where
+    I2C: Write<Error = E> + WriteRead<Error = E>,
+{
+    type Error = E;
 
-        // SP
-        self.raw_i2c_stop()
+    fn write_read(&mut self, addr: u8, output: &[u8], input: &mut [u8]) -> Result<(), E> {
+        self.select()?;
+        self.i2c.write_read(addr, output, input)
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::mock::{Condition, FlakyPin, Line, MockPin, MockTimer, Recorder, WavePin};
+    use core::cell::{Cell, RefCell};
+
+    type Rec = RefCell<Recorder<1024>>;
+
+    fn bus(rec: &Rec) -> I2cBB<MockPin<'_, 1024>, MockPin<'_, 1024>, MockTimer<'_, 1024>> {
+        I2cBB::new(
+            MockPin::new(rec, Line::Scl),
+            MockPin::new(rec, Line::Sda),
+            MockTimer::new(rec),
+        )
+    }
+
+    fn bits(byte: u8) -> [bool; 8] {
+        let mut bits = [false; 8];
+        for (i, bit) in bits.iter_mut().enumerate() {
+            *bit = byte & (0x80 >> i) != 0;
+        }
+        bits
+    }
+
+    /// ACK bits driven after each byte of a raw read, `true` for an ACK,
+    /// written to `out`; returns the number of bytes read
+    fn driven_acks(rec: &Rec, out: &mut [bool]) -> usize {
+        let mut sda = true;
+        let mut pulses = 0;
+        for event in rec.borrow().events() {
+            match event.line {
+                Line::Sda => sda = event.level,
+                Line::Scl if event.level => {
+                    if pulses % 9 == 8 {
+                        out[pulses / 9] = !sda;
+                    }
+                    pulses += 1;
+                }
+                _ => {}
+            }
+        }
+        pulses / 9
+    }
+
+    fn assert_conditions(rec: &Rec, expected: &[Condition]) {
+        let rec = rec.borrow();
+        assert_eq!(rec.check_i2c(), Ok(()));
+        assert!(rec
+            .conditions()
+            .map(|(_, condition)| condition)
+            .eq(expected.iter().copied()));
+    }
+
+    #[test]
+    fn write() {
+        let rec: Rec = RefCell::new(Recorder::new());
+        let mut i2c = bus(&rec);
+        rec.borrow_mut().queue_reads(&[false, false, false]);
+        i2c.write(0x50, &[0x12, 0xab]).unwrap();
+        assert_conditions(&rec, &[Condition::Start, Condition::Stop]);
+    }
+
+    #[test]
+    fn read() {
+        let rec: Rec = RefCell::new(Recorder::new());
+        let mut i2c = bus(&rec);
+        rec.borrow_mut().queue_reads(&[false]);
+        rec.borrow_mut().queue_reads(&bits(0xa5));
+        rec.borrow_mut().queue_reads(&bits(0x3c));
+        let mut input = [0; 2];
+        i2c.read(0x50, &mut input).unwrap();
+        assert_eq!(input, [0xa5, 0x3c]);
+        assert_conditions(&rec, &[Condition::Start, Condition::Stop]);
+    }
+
+    #[test]
+    fn write_read() {
+        let rec: Rec = RefCell::new(Recorder::new());
+        let mut i2c = bus(&rec);
+        rec.borrow_mut().queue_reads(&[false, false, false]);
+        rec.borrow_mut().queue_reads(&bits(0x5a));
+        let mut input = [0];
+        i2c.write_read(0x50, &[0x10], &mut input).unwrap();
+        assert_eq!(input, [0x5a]);
+        assert_conditions(&rec, &[Condition::Start, Condition::Start, Condition::Stop]);
+    }
+
+    #[test]
+    fn empty_write_addresses_device() {
+        let rec: Rec = RefCell::new(Recorder::new());
+        let mut i2c = bus(&rec);
+        rec.borrow_mut().queue_reads(&[false]);
+        i2c.write(0x50, &[]).unwrap();
+        assert_conditions(&rec, &[Condition::Start, Condition::Stop]);
+    }
+
+    #[test]
+    fn empty_read_is_skipped() {
+        let rec: Rec = RefCell::new(Recorder::new());
+        let mut i2c = bus(&rec);
+        i2c.read(0x50, &mut []).unwrap();
+        assert!(rec.borrow().events().is_empty());
+    }
+
+    #[test]
+    fn write_read_empty_output() {
+        let rec: Rec = RefCell::new(Recorder::new());
+        let mut i2c = bus(&rec);
+        rec.borrow_mut().queue_reads(&[false, false]);
+        rec.borrow_mut().queue_reads(&bits(0x5a));
+        let mut input = [0];
+        i2c.write_read(0x50, &[], &mut input).unwrap();
+        assert_eq!(input, [0x5a]);
+        assert_conditions(&rec, &[Condition::Start, Condition::Start, Condition::Stop]);
+    }
+
+    #[test]
+    fn write_read_empty_input() {
+        let rec: Rec = RefCell::new(Recorder::new());
+        let mut i2c = bus(&rec);
+        rec.borrow_mut().queue_reads(&[false, false]);
+        i2c.write_read(0x50, &[0x10], &mut []).unwrap();
+        assert_conditions(&rec, &[Condition::Start, Condition::Stop]);
+    }
+
+    #[test]
+    fn write_read_both_empty() {
+        let rec: Rec = RefCell::new(Recorder::new());
+        let mut i2c = bus(&rec);
+        rec.borrow_mut().queue_reads(&[false]);
+        i2c.write_read(0x50, &[], &mut []).unwrap();
+        assert_conditions(&rec, &[Condition::Start, Condition::Stop]);
+    }
+
+    #[test]
+    fn held_write_then_read() {
+        let rec: Rec = RefCell::new(Recorder::new());
+        let mut i2c = bus(&rec);
+        rec.borrow_mut().queue_reads(&[false, false]);
+        i2c.write_no_stop(0x50, &[0x10]).unwrap();
+        // SCL is held low until the transaction continues
+        let scl = rec
+            .borrow()
+            .events()
+            .iter()
+            .rev()
+            .find(|event| event.line == Line::Scl)
+            .map(|event| event.level);
+        assert_eq!(scl, Some(false));
+
+        rec.borrow_mut().queue_reads(&[false]);
+        rec.borrow_mut().queue_reads(&bits(0x5a));
+        let mut input = [0];
+        i2c.continue_read(0x50, &mut input).unwrap();
+        assert_eq!(input, [0x5a]);
+        assert_conditions(&rec, &[Condition::Start, Condition::Start, Condition::Stop]);
+    }
+
+    #[test]
+    fn settle_ticks_follow_every_release() {
+        let recs = [RefCell::new(Recorder::new()), RefCell::new(Recorder::new())];
+        for (settle, rec) in recs.iter().enumerate() {
+            let mut i2c = bus(rec);
+            i2c.set_settle_ticks(settle as u32);
+            rec.borrow_mut().queue_reads(&[false, false]);
+            i2c.write(0x50, &[0xff]).unwrap();
+            assert_eq!(rec.borrow().check_i2c(), Ok(()));
+        }
+        let (plain, settled) = (recs[0].borrow(), recs[1].borrow());
+
+        // the same edges, each release followed by one more tick
+        assert_eq!(plain.events().len(), settled.events().len());
+        for (a, b) in plain.events().iter().zip(settled.events()) {
+            assert_eq!((a.line, a.level), (b.line, b.level));
+        }
+        let releases = plain.events().iter().filter(|event| event.level).count();
+        assert_eq!(settled.time(), plain.time() + releases as u32);
+        for pair in settled.events().windows(2) {
+            if pair[0].level {
+                assert!(pair[1].time > pair[0].time, "{:?}", pair);
+            }
+        }
+    }
+
+    /// Time of the `n`th rising SCL edge, counting from zero with the one
+    /// before the START, of a plain `write(0x50, &[0x12])`, and the duration
+    /// of the whole write
+    fn scl_rise(n: usize) -> (u32, u32) {
+        let rec: Rec = RefCell::new(Recorder::new());
+        let mut i2c = bus(&rec);
+        rec.borrow_mut().queue_reads(&[false, false]);
+        i2c.write(0x50, &[0x12]).unwrap();
+        let rec = rec.borrow();
+        let rise = rec
+            .events()
+            .iter()
+            .filter(|event| event.line == Line::Scl && event.level)
+            .nth(n)
+            .unwrap()
+            .time;
+        (rise, rec.time())
+    }
+
+    #[test]
+    fn clock_stretching_is_tolerated() {
+        // a slave holds SCL low for 3 ticks when the third address bit is
+        // clocked
+        let (stretch_at, plain_time) = scl_rise(3);
+        let scl = |tick| !(stretch_at..stretch_at + 3).contains(&tick);
+        let rec: Rec = RefCell::new(Recorder::new());
+        let mut i2c = I2cBB::new(
+            WavePin::new(&rec, Line::Scl, &scl),
+            MockPin::new(&rec, Line::Sda),
+            MockTimer::new(&rec),
+        );
+        i2c.set_clock_stretch_timeout(Some(5));
+        rec.borrow_mut().queue_reads(&[false, false]);
+        i2c.write(0x50, &[0x12]).unwrap();
+        assert_conditions(&rec, &[Condition::Start, Condition::Stop]);
+        assert_eq!(rec.borrow().time(), plain_time + 3);
+    }
+
+    #[test]
+    fn clock_stretching_times_out() {
+        let (stretch_at, _) = scl_rise(3);
+        let scl = |tick| !(stretch_at..stretch_at + 10).contains(&tick);
+        let rec: Rec = RefCell::new(Recorder::new());
+        let mut i2c = I2cBB::new(
+            WavePin::new(&rec, Line::Scl, &scl),
+            MockPin::new(&rec, Line::Sda),
+            MockTimer::new(&rec),
+        );
+        i2c.set_clock_stretch_timeout(Some(5));
+        assert_eq!(i2c.write(0x50, &[0x12]), Err(Error::Timeout));
+    }
+
+    #[test]
+    fn arbitration_lost_on_sent_one() {
+        // another master pulls SDA low while the first address bit, a 1, is
+        // clocked
+        let (bit_at, _) = scl_rise(1);
+        let sda = |tick| !(bit_at..bit_at + 2).contains(&tick);
+        let rec: Rec = RefCell::new(Recorder::new());
+        let mut i2c = I2cBB::new(
+            WavePin::new(&rec, Line::Scl, &|_| true),
+            WavePin::new(&rec, Line::Sda, &sda),
+            MockTimer::new(&rec),
+        );
+        i2c.set_clock_stretch_timeout(Some(5));
+        i2c.set_arbitration_check(true);
+        assert_eq!(i2c.write(0x50, &[0x12]), Err(Error::ArbitrationLost));
+        // both lines released, without a STOP
+        assert_conditions(&rec, &[Condition::Start]);
+    }
+
+    #[test]
+    fn bus_health_of_idle_bus() {
+        let rec: Rec = RefCell::new(Recorder::new());
+        let mut i2c = bus(&rec);
+        let health = i2c.bus_health().unwrap();
+        assert_eq!(
+            health,
+            BusHealth {
+                scl_idle_high: true,
+                sda_idle_high: true,
+                recovery_needed: false,
+                recovered: false,
+                start_stop_ok: true,
+            }
+        );
+        assert!(health.is_healthy());
+        assert_conditions(&rec, &[Condition::Start, Condition::Stop]);
+    }
+
+    #[test]
+    fn bus_health_recovers_stuck_sda() {
+        // a slave holds SDA low until it has seen two more clock pulses
+        let sda = |tick| tick >= 4;
+        let rec: Rec = RefCell::new(Recorder::new());
+        let mut i2c = I2cBB::new(
+            MockPin::new(&rec, Line::Scl),
+            WavePin::new(&rec, Line::Sda, &sda),
+            MockTimer::new(&rec),
+        );
+        let health = i2c.bus_health().unwrap();
+        assert_eq!(
+            health,
+            BusHealth {
+                scl_idle_high: true,
+                sda_idle_high: false,
+                recovery_needed: true,
+                recovered: true,
+                start_stop_ok: true,
+            }
+        );
+        assert!(!health.is_healthy());
+        let pulses = rec
+            .borrow()
+            .events()
+            .iter()
+            .filter(|event| event.line == Line::Scl && !event.level && event.time < 4)
+            .count();
+        assert_eq!(pulses, 2);
+        assert_eq!(rec.borrow().check_i2c(), Ok(()));
+    }
+
+    #[test]
+    fn recover_bus_gives_up_after_nine_pulses() {
+        let rec: Rec = RefCell::new(Recorder::new());
+        let mut i2c = I2cBB::new(
+            MockPin::new(&rec, Line::Scl),
+            WavePin::new(&rec, Line::Sda, &|_| false),
+            MockTimer::new(&rec),
+        );
+        assert_eq!(i2c.recover_bus(), Ok(false));
+        let pulses = rec
+            .borrow()
+            .events()
+            .iter()
+            .filter(|event| event.line == Line::Scl && !event.level)
+            .count();
+        assert_eq!(pulses, 9);
+    }
+
+    #[test]
+    fn bus_health_reports_pin_error() {
+        let rec: Rec = RefCell::new(Recorder::new());
+        let (scl_fault, sda_fault) = (Cell::new(None), Cell::new(None));
+        let mut i2c = I2cBB::new(
+            FlakyPin::new(&rec, Line::Scl, &scl_fault),
+            FlakyPin::new(&rec, Line::Sda, &sda_fault),
+            MockTimer::new(&rec),
+        );
+        // the idle check reads SDA once, the START then fails to drive it
+        sda_fault.set(Some(1));
+        assert_eq!(i2c.bus_health(), Err(Error::Bus(())));
+    }
+
+    #[test]
+    fn sda_error_mid_byte() {
+        let rec: Rec = RefCell::new(Recorder::new());
+        let (scl_fault, sda_fault) = (Cell::new(None), Cell::new(None));
+        let mut i2c = I2cBB::new(
+            FlakyPin::new(&rec, Line::Scl, &scl_fault),
+            FlakyPin::new(&rec, Line::Sda, &sda_fault),
+            MockTimer::new(&rec),
+        );
+        rec.borrow_mut().queue_reads(&[false]);
+        rec.borrow_mut().queue_reads(&bits(0xa5));
+        // START and address take 21 SDA accesses, the data byte then
+        // releases SDA and fails reading its fourth bit
+        sda_fault.set(Some(25));
+        let mut input = [0];
+        assert_eq!(i2c.read(0x50, &mut input), Err(Error::Bus(())));
+        assert_eq!(i2c.take_partial(), (0xa0, 3));
+
+        let rec = rec.borrow();
+        let conditions = rec.conditions().map(|(_, condition)| condition);
+        assert_eq!(conditions.last(), Some(Condition::Stop));
+        let last = rec.events().last().unwrap();
+        assert_eq!((last.line, last.level), (Line::Sda, true));
+    }
+
+    /// Three SDA samples per bit of `byte`, MSB first, with one of each three
+    /// flipped
+    fn noisy_bits(byte: u8) -> [bool; 24] {
+        let mut samples = [false; 24];
+        for (i, sample) in samples.iter_mut().enumerate() {
+            let bit = byte & (0x80 >> (i / 3)) != 0;
+            *sample = bit ^ (i % 3 == i / 3 % 3);
+        }
+        samples
+    }
+
+    #[test]
+    fn sample_count_outvotes_one_sample() {
+        let rec: Rec = RefCell::new(Recorder::new());
+        let mut i2c = bus(&rec);
+        i2c.set_sample_count(3);
+        // address ACK read as low twice out of three
+        rec.borrow_mut().queue_reads(&[false, true, false]);
+        rec.borrow_mut().queue_reads(&noisy_bits(0xa5));
+        rec.borrow_mut().queue_reads(&noisy_bits(0x3c));
+        let mut input = [0; 2];
+        i2c.read(0x50, &mut input).unwrap();
+        assert_eq!(input, [0xa5, 0x3c]);
+    }
+
+    #[test]
+    fn poll_write_read_matches_blocking() {
+        let (blocking, polled): (Rec, Rec) =
+            (RefCell::new(Recorder::new()), RefCell::new(Recorder::new()));
+        for rec in [&blocking, &polled] {
+            // ACKs of both addresses and the two written bytes
+            rec.borrow_mut().queue_reads(&[false; 4]);
+            rec.borrow_mut().queue_reads(&bits(0x5a));
+            rec.borrow_mut().queue_reads(&bits(0xc3));
+        }
+
+        let mut input = [0; 2];
+        bus(&blocking)
+            .write_read(0x50, &[0x10, 0x20], &mut input)
+            .unwrap();
+        assert_eq!(input, [0x5a, 0xc3]);
+
+        let mut i2c = bus(&polled);
+        let mut input = [0; 2];
+        let mut polls = 0;
+        while let Err(nb::Error::WouldBlock) = i2c.poll_write_read(0x50, &[0x10, 0x20], &mut input)
+        {
+            polls += 1;
+        }
+        assert_eq!(input, [0x5a, 0xc3]);
+        assert!(polls > 1);
+        assert_conditions(
+            &polled,
+            &[Condition::Start, Condition::Start, Condition::Stop],
+        );
+        assert_eq!(polled.borrow().events(), blocking.borrow().events());
+    }
+
+    #[test]
+    fn with_retry_backs_off_after_nack() {
+        let rec: Rec = RefCell::new(Recorder::new());
+        let mut i2c = bus(&rec);
+        let mut starts = [0; 4];
+        let mut calls = 0;
+        let result: Result<(), _> = i2c.with_retry(3, |_| {
+            starts[calls] = rec.borrow().time();
+            calls += 1;
+            Err(Error::NoAck)
+        });
+        assert_eq!(result, Err(Error::NoAck));
+        assert_eq!(calls, 4);
+
+        // each retry follows a STOP of four ticks and a growing backoff
+        let mut gaps = starts.windows(2).map(|pair| pair[1] - pair[0]);
+        for attempt in 1..=3 {
+            assert_eq!(gaps.next(), Some(4 + attempt * RETRY_BACKOFF_TICKS));
+        }
+        let rec = rec.borrow();
+        assert_eq!(
+            rec.conditions()
+                .filter(|(_, c)| *c == Condition::Stop)
+                .count(),
+            3
+        );
+    }
+
+    #[test]
+    fn with_retry_returns_other_errors_at_once() {
+        let rec: Rec = RefCell::new(Recorder::new());
+        let mut i2c = bus(&rec);
+        let mut calls = 0;
+        let result: Result<(), _> = i2c.with_retry(3, |_| {
+            calls += 1;
+            Err(Error::InvalidData)
+        });
+        assert_eq!(result, Err(Error::InvalidData));
+        assert_eq!(calls, 1);
+        assert!(rec.borrow().events().is_empty());
+        assert_eq!(rec.borrow().time(), 0);
+    }
+
+    #[test]
+    fn with_retry_leaves_bus_after_arbitration_loss() {
+        let rec: Rec = RefCell::new(Recorder::new());
+        let mut i2c = bus(&rec);
+        let mut calls = 0;
+        let result: Result<(), _> = i2c.with_retry(2, |_| {
+            calls += 1;
+            Err(Error::ArbitrationLost)
+        });
+        assert_eq!(result, Err(Error::ArbitrationLost));
+        assert_eq!(calls, 3);
+        assert!(rec.borrow().events().is_empty());
+        // the backoffs of both retries, without a STOP
+        assert_eq!(rec.borrow().time(), (1 + 2) * RETRY_BACKOFF_TICKS);
+    }
+
+    #[test]
+    fn with_retry_returns_stop_error() {
+        let rec: Rec = RefCell::new(Recorder::new());
+        let (scl_fault, sda_fault) = (Cell::new(Some(0)), Cell::new(None));
+        let mut i2c = I2cBB::new(
+            FlakyPin::new(&rec, Line::Scl, &scl_fault),
+            FlakyPin::new(&rec, Line::Sda, &sda_fault),
+            MockTimer::new(&rec),
+        );
+        let mut calls = 0;
+        let result: Result<(), _> = i2c.with_retry(3, |_| {
+            calls += 1;
+            Err(Error::NoAck)
+        });
+        assert_eq!(result, Err(Error::Bus(())));
+        assert_eq!(calls, 1);
+    }
+
+    /// Bus remembering the last write, as `(address, first byte)`
+    struct LastWrite(Option<(u8, u8)>);
+
+    impl Write for LastWrite {
+        type Error = ();
+
+        fn write(&mut self, addr: u8, output: &[u8]) -> Result<(), ()> {
+            self.0 = Some((addr, output[0]));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn muxed_i2c_rejects_channel_above_seven() {
+        let mut bus = LastWrite(None);
+        assert!(matches!(
+            MuxedI2c::new(&mut bus, 0x70, 8),
+            Err(InvalidChannel(8))
+        ));
+
+        let mut muxed = MuxedI2c::new(&mut bus, 0x70, 7).unwrap();
+        muxed.select().unwrap();
+        assert_eq!(muxed.i2c.0, Some((0x70, 0x80)));
+
+        assert_eq!(muxed.set_channel(9), Err(InvalidChannel(9)));
+        assert_eq!(muxed.channel(), 7);
+        muxed.set_channel(2).unwrap();
+        muxed.select().unwrap();
+        assert_eq!(muxed.i2c.0, Some((0x70, 0x04)));
+    }
+
+    #[test]
+    fn calibrate_counts_ticks() {
+        let rec: Rec = RefCell::new(Recorder::new());
+        let mut i2c = bus(&rec);
+        assert_eq!(i2c.calibrate(5), Ok(10));
+        {
+            let rec = rec.borrow();
+            assert_eq!(rec.time(), 10);
+            let mut scl = rec.events().iter().filter(|e| e.line == Line::Scl);
+            assert_eq!(scl.clone().filter(|e| e.level).count(), 5);
+            assert_eq!(scl.next_back().map(|e| e.level), Some(true));
+            assert!(rec.conditions().next().is_none());
+        }
+
+        // settle ticks lengthen every period, and releasing SDA once
+        i2c.set_settle_ticks(1);
+        assert_eq!(i2c.calibrate(4), Ok(4 * 3 + 1));
+    }
+
+    #[test]
+    fn read_pattern_wraps() {
+        let rec: Rec = RefCell::new(Recorder::new());
+        let mut i2c = bus(&rec);
+        rec.borrow_mut().queue_reads(&bits(0x12));
+        rec.borrow_mut().queue_reads(&bits(0x34));
+        let mut buf = [0; 5];
+        i2c.read_pattern(&mut buf, &[true, true, false]).unwrap();
+        assert_eq!(buf, [0x12, 0x34, 0xff, 0xff, 0xff]);
+        let mut acks = [false; 5];
+        assert_eq!(driven_acks(&rec, &mut acks), 5);
+        assert_eq!(acks, [true, true, false, true, true]);
+    }
+
+    #[test]
+    fn read_pattern_empty_nacks_last() {
+        let rec: Rec = RefCell::new(Recorder::new());
+        let mut i2c = bus(&rec);
+        let mut buf = [0; 3];
+        i2c.read_pattern(&mut buf, &[]).unwrap();
+        let mut acks = [false; 3];
+        assert_eq!(driven_acks(&rec, &mut acks), 3);
+        assert_eq!(acks, [true, true, false]);
+    }
+
+    fn acks_with<F>(len: usize, ack: F) -> [bool; 4]
+    where
+        F: FnMut(usize) -> bool,
+    {
+        let rec: Rec = RefCell::new(Recorder::new());
+        let mut i2c = bus(&rec);
+        let mut buf = [0; 4];
+        i2c.read_with_acks(&mut buf[..len], ack).unwrap();
+        let mut acks = [false; 4];
+        assert_eq!(driven_acks(&rec, &mut acks), len);
+        acks
+    }
+
+    #[test]
+    fn read_with_acks_nack_first() {
+        assert_eq!(acks_with(4, |i| i != 0), [false, true, true, true]);
+    }
+
+    #[test]
+    fn read_with_acks_nack_last() {
+        assert_eq!(acks_with(4, |i| i != 3), [true, true, true, false]);
+    }
+
+    #[test]
+    fn read_with_acks_callback_terminates_early() {
+        // the callback sees each byte index once, in order, and NACKs every
+        // byte once its budget of one ACK is spent, so the slave stops
+        // sending after the first byte
+        let mut expected = 0;
+        let mut budget = 2;
+        let acks = acks_with(3, |i| {
+            assert_eq!(i, expected);
+            expected += 1;
+            budget -= 1;
+            budget > 0
+        });
+        assert_eq!(acks, [true, false, false, false]);
+        assert_eq!(expected, 3);
+    }
+
+    #[test]
+    fn usable_after_sda_error() {
+        let rec: Rec = RefCell::new(Recorder::new());
+        let (scl_fault, sda_fault) = (Cell::new(None), Cell::new(None));
+        let mut i2c = I2cBB::new(
+            FlakyPin::new(&rec, Line::Scl, &scl_fault),
+            FlakyPin::new(&rec, Line::Sda, &sda_fault),
+            MockTimer::new(&rec),
+        );
+
+        // fail in the middle of the address byte
+        sda_fault.set(Some(4));
+        assert_eq!(i2c.write(0x50, &[0x12]), Err(Error::Bus(())));
+        assert_eq!(rec.borrow().check_i2c(), Ok(()));
+
+        rec.borrow_mut().clear();
+        rec.borrow_mut().queue_reads(&[false, false]);
+        i2c.write(0x50, &[0x12]).unwrap();
+        assert_conditions(&rec, &[Condition::Start, Condition::Stop]);
+    }
+
+    #[test]
+    fn nack_releases_bus() {
+        let rec: Rec = RefCell::new(Recorder::new());
+        let mut i2c = bus(&rec);
+        assert_eq!(i2c.write(0x50, &[0x12]), Err(Error::NoAck));
+        assert_conditions(&rec, &[Condition::Start, Condition::Stop]);
+    }
+
+    fn timing(
+        i2c: &I2cBB<MockPin<'_, 1024>, MockPin<'_, 1024>, MockTimer<'_, 1024>>,
+    ) -> (u32, u32, u32, u32, BitOrder, u8) {
+        (
+            i2c.start_hold_ticks,
+            i2c.stop_hold_ticks,
+            i2c.rise_ticks,
+            i2c.settle_ticks,
+            i2c.bit_order,
+            i2c.sample_count,
+        )
+    }
+
+    #[test]
+    fn builder_applies_settings() {
+        let rec: Rec = RefCell::new(Recorder::new());
+        let builder = || {
+            I2cBuilder::new()
+                .scl(MockPin::new(&rec, Line::Scl))
+                .sda(MockPin::new(&rec, Line::Sda))
+                .clk(MockTimer::new(&rec))
+        };
+
+        assert_eq!(timing(&builder().build()), timing(&bus(&rec)));
+
+        let i2c = builder()
+            .hold_ticks(3, 4)
+            .rise_ticks(2)
+            .settle_ticks(5)
+            .bit_order(BitOrder::LSBFirst)
+            .sample_count(3)
+            .build();
+        assert_eq!(timing(&i2c), (3, 4, 2, 5, BitOrder::LSBFirst, 3));
+
+        // a preset overrides the ticks set before it, later setters win
+        let i2c = builder()
+            .hold_ticks(3, 4)
+            .settle_ticks(5)
+            .timing_preset(TimingPreset::FastPlus)
+            .rise_ticks(3)
+            .build();
+        assert_eq!(timing(&i2c), (2, 2, 3, 0, BitOrder::MSBFirst, 1));
     }
 }
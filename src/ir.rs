@@ -0,0 +1,91 @@
+//! Infrared remote control transmitter
+//!
+//! This implementation consumes the following hardware resources:
+//! - Periodic timer to mark pulse widths
+//! - Output GPIO pin driving the IR LED, or gating an external carrier
+//!
+//! IR remote protocols such as NEC and RC5 are sequences of marks (carrier
+//! on) and spaces (carrier off). [IrTx::send_pulses] plays such a sequence
+//! from a precomputed slice of `(level, ticks)` pairs, so any protocol can be
+//! encoded by the application. With a 38 kHz carrier generated externally,
+//! e.g. by a PWM channel ANDed with the pin, a timer tick of a few tens of
+//! microseconds is enough; for unmodulated receivers the pin drives the LED
+//! directly.
+//!
+//! The timing is only as accurate as the timer ticks, so interrupts should
+//! be disabled while sending if they can take longer than a tick.
+//!
+
+use embedded_hal::digital::v2::OutputPin;
+use embedded_hal::timer::{CountDown, Periodic};
+
+use crate::timing::clock_edge;
+
+/// IR transmitter error
+#[derive(Debug)]
+pub enum Error<E> {
+    /// GPIO error
+    Bus(E),
+}
+
+impl<E> core::fmt::Display for Error<E>
+where
+    E: core::fmt::Debug,
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Error::Bus(e) => crate::error::fmt_bus_error(f, None, e),
+        }
+    }
+}
+
+#[cfg(feature = "core-error")]
+impl<E> core::error::Error for Error<E>
+where
+    E: core::error::Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            Error::Bus(e) => Some(e),
+        }
+    }
+}
+
+/// Bit banging IR transmitter
+pub struct IrTx<OUT, Timer>
+where
+    OUT: OutputPin,
+    Timer: CountDown + Periodic,
+{
+    out: OUT,
+    timer: Timer,
+}
+
+impl<OUT, Timer, E> IrTx<OUT, Timer>
+where
+    OUT: OutputPin<Error = E>,
+    Timer: CountDown + Periodic,
+{
+    /// Create instance, with the output off (low)
+    pub fn new(mut out: OUT, timer: Timer) -> Self {
+        out.set_low().unwrap_or(());
+        IrTx { out, timer }
+    }
+
+    /// Release the pin and timer
+    pub fn free(self) -> (OUT, Timer) {
+        (self.out, self.timer)
+    }
+
+    /// Send marks and spaces
+    ///
+    /// Each `(level, ticks)` pair drives the output to `level` (high for a
+    /// mark) for `ticks` timer ticks. The output is switched off afterwards.
+    pub fn send_pulses(&mut self, pulses: &[(bool, u32)]) -> Result<(), crate::ir::Error<E>> {
+        for (level, ticks) in pulses {
+            clock_edge(&mut self.out, *level, &mut self.timer, *ticks).map_err(Error::Bus)?;
+        }
+
+        self.out.set_low().map_err(Error::Bus)
+    }
+}
@@ -10,6 +10,15 @@
 #![no_std]
 #![deny(missing_docs)]
 
+pub mod console;
+pub mod crc;
+pub mod error;
 pub mod i2c;
+pub mod ir;
+pub mod midi;
+#[cfg(feature = "mock")]
+pub mod mock;
 pub mod serial;
 pub mod spi;
+pub mod timing;
+pub mod ws2812;
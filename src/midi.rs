@@ -0,0 +1,222 @@
+//! MIDI over bit banged serial
+//!
+//! MIDI uses 31250 baud, 8 data bits, no parity and one stop bit, which is
+//! the default framing of [Serial](crate::serial::Serial). Configure its timer
+//! for 31250 baud as described in the [serial](crate::serial) module.
+//!
+//! [Midi] adds sending of complete messages and an incremental parser that
+//! handles running status, i.e. data bytes arriving without a new status byte
+//! reuse the previous one.
+//!
+
+use embedded_hal::serial::{Read, Write};
+use nb::block;
+
+/// A complete MIDI message
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct MidiMessage {
+    /// Status byte
+    pub status: u8,
+    data: [u8; 2],
+    len: u8,
+}
+
+impl MidiMessage {
+    /// Data bytes following the status byte
+    pub fn data(&self) -> &[u8] {
+        &self.data[..self.len as usize]
+    }
+}
+
+/// Number of data bytes following `status`, `None` for variable-length
+/// system exclusive messages.
+fn data_len(status: u8) -> Option<u8> {
+    match status {
+        0x80..=0xBF | 0xE0..=0xEF | 0xF2 => Some(2),
+        0xC0..=0xDF | 0xF1 | 0xF3 => Some(1),
+        0xF0 => None,
+        _ => Some(0),
+    }
+}
+
+/// MIDI helper over a serial port
+pub struct Midi<S> {
+    serial: S,
+    running_status: Option<u8>,
+    data: [u8; 2],
+    received: u8,
+}
+
+impl<S, E> Midi<S>
+where
+    S: Read<u8, Error = E> + Write<u8, Error = E>,
+{
+    /// Create instance
+    pub fn new(serial: S) -> Self {
+        Midi {
+            serial,
+            running_status: None,
+            data: [0; 2],
+            received: 0,
+        }
+    }
+
+    /// Release the serial port
+    pub fn free(self) -> S {
+        self.serial
+    }
+
+    /// Send a status byte followed by its data bytes
+    pub fn send_message(&mut self, status: u8, data: &[u8]) -> Result<(), E> {
+        block!(self.serial.write(status))?;
+        for byte in data {
+            block!(self.serial.write(*byte))?;
+        }
+        Ok(())
+    }
+
+    /// Receive one byte and feed it to the parser
+    ///
+    /// Returns `WouldBlock` until a complete message has been received.
+    /// Real-time messages (0xF8..=0xFF) are returned immediately and do not
+    /// interrupt a message in progress. System exclusive data is skipped.
+    pub fn poll_message(&mut self) -> nb::Result<MidiMessage, E> {
+        let byte = self.serial.read()?;
+
+        if byte >= 0xF8 {
+            return Ok(MidiMessage {
+                status: byte,
+                data: [0; 2],
+                len: 0,
+            });
+        }
+
+        if byte & 0x80 != 0 {
+            self.received = 0;
+            return match data_len(byte) {
+                Some(0) => {
+                    self.running_status = None;
+                    Ok(MidiMessage {
+                        status: byte,
+                        data: [0; 2],
+                        len: 0,
+                    })
+                }
+                Some(_) => {
+                    self.running_status = Some(byte);
+                    Err(nb::Error::WouldBlock)
+                }
+                None => {
+                    self.running_status = None;
+                    Err(nb::Error::WouldBlock)
+                }
+            };
+        }
+
+        let status = match self.running_status {
+            Some(status) => status,
+            None => return Err(nb::Error::WouldBlock),
+        };
+        let len = match data_len(status) {
+            Some(len) if len > 0 => len,
+            _ => return Err(nb::Error::WouldBlock),
+        };
+
+        self.data[self.received as usize] = byte;
+        self.received += 1;
+        if self.received < len {
+            return Err(nb::Error::WouldBlock);
+        }
+
+        self.received = 0;
+        // system common messages do not establish running status
+        if status >= 0xF0 {
+            self.running_status = None;
+        }
+        Ok(MidiMessage {
+            status,
+            data: self.data,
+            len,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::convert::Infallible;
+
+    /// Serial port replaying `input`, discarding writes
+    struct Script<'a> {
+        input: &'a [u8],
+    }
+
+    impl Read<u8> for Script<'_> {
+        type Error = Infallible;
+
+        fn read(&mut self) -> nb::Result<u8, Infallible> {
+            let (byte, rest) = self.input.split_first().ok_or(nb::Error::WouldBlock)?;
+            self.input = rest;
+            Ok(*byte)
+        }
+    }
+
+    impl Write<u8> for Script<'_> {
+        type Error = Infallible;
+
+        fn write(&mut self, _word: u8) -> nb::Result<(), Infallible> {
+            Ok(())
+        }
+
+        fn flush(&mut self) -> nb::Result<(), Infallible> {
+            Ok(())
+        }
+    }
+
+    /// Poll until the script runs out, checking each message against the
+    /// expected status and data bytes
+    fn check(input: &[u8], expected: &[(u8, &[u8])]) {
+        let mut midi = Midi::new(Script { input });
+        let mut count = 0;
+        while !midi.serial.input.is_empty() {
+            if let Ok(message) = midi.poll_message() {
+                let (status, data) = expected[count];
+                assert_eq!((message.status, message.data()), (status, data));
+                count += 1;
+            }
+        }
+        assert_eq!(count, expected.len());
+    }
+
+    #[test]
+    fn poll_message_parses_scripted_stream() {
+        check(
+            &[
+                // note on, then running status with a clock byte mid-message
+                0x90, 0x3C, 0x40, 0x3E, 0xF8, 0x41,
+                // system exclusive data is skipped, and so are data bytes
+                // after it, as it cancels running status
+                0xF0, 0x01, 0x02, 0xF7, 0x03, // program change with running status
+                0xC1, 0x05, 0x06, // song position cancels running status
+                0xF2, 0x10, 0x20, 0x07, // MTC quarter frame
+                0xF1, 0x31,
+            ],
+            &[
+                (0x90, &[0x3C, 0x40]),
+                (0xF8, &[]),
+                (0x90, &[0x3E, 0x41]),
+                (0xF7, &[]),
+                (0xC1, &[0x05]),
+                (0xC1, &[0x06]),
+                (0xF2, &[0x10, 0x20]),
+                (0xF1, &[0x31]),
+            ],
+        );
+    }
+
+    #[test]
+    fn poll_message_restarts_on_new_status() {
+        // a status byte mid-message drops the partial message
+        check(&[0x90, 0x3C, 0x80, 0x3C, 0x00], &[(0x80, &[0x3C, 0x00])]);
+    }
+}
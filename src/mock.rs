@@ -0,0 +1,455 @@
+//! Recording mock pins and timer for checking generated waveforms
+//!
+//! Enabled with the `mock` feature. A [Recorder] collects every level change
+//! of its [MockPin]s, timestamped with the number of [MockTimer] ticks
+//! waited so far, into a fixed-size buffer. Pins and timer borrow the
+//! recorder through a [RefCell], so the events can be inspected while the
+//! driver still owns them.
+//!
+//! Reads return the level the pin was last driven to, unless responses have
+//! been queued with [Recorder::queue_reads], e.g. to let a simulated slave
+//! ACK or send data.
+//!
+//! [check_i2c](Recorder::check_i2c) verifies the basic I2C edge ordering of
+//! a recording made with an [I2cBB](crate::i2c::I2cBB) on pins
+//! [Line::Scl] and [Line::Sda], and [conditions](Recorder::conditions) lists
+//! its START and STOP conditions, e.g. to check that `write_read` uses a
+//! repeated START:
+//!
+//! ```
+//! # use core::cell::RefCell;
+//! # use bitbang_hal::i2c::I2cBB;
+//! # use bitbang_hal::mock::{Condition, Line, MockPin, MockTimer, Recorder};
+//! # use embedded_hal::blocking::i2c::WriteRead;
+//! let rec = RefCell::new(Recorder::<1024>::new());
+//! let mut i2c = I2cBB::new(
+//!     MockPin::new(&rec, Line::Scl),
+//!     MockPin::new(&rec, Line::Sda),
+//!     MockTimer::new(&rec),
+//! );
+//! rec.borrow_mut().queue_reads(&[false, false, false]);
+//! i2c.write_read(0x50, &[0x10], &mut [0]).unwrap();
+//!
+//! let rec = rec.borrow();
+//! let mut conditions = rec.conditions().map(|(_, condition)| condition);
+//! assert_eq!(conditions.next(), Some(Condition::Start));
+//! assert_eq!(conditions.next(), Some(Condition::Start));
+//! assert_eq!(conditions.next(), Some(Condition::Stop));
+//! assert_eq!(conditions.next(), None);
+//! ```
+//!
+
+use core::cell::RefCell;
+use core::convert::Infallible;
+
+use embedded_hal::digital::v2::{InputPin, OutputPin};
+use embedded_hal::timer::{CountDown, Periodic};
+use void::Void;
+
+/// Bus line a [MockPin] represents
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Line {
+    /// I2C clock
+    Scl,
+    /// I2C data
+    Sda,
+    /// Any other line, identified by number
+    Other(u8),
+}
+
+/// A recorded level change
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Event {
+    /// Timer ticks waited before the change
+    pub time: u32,
+    /// Line that changed
+    pub line: Line,
+    /// New level
+    pub level: bool,
+}
+
+/// I2C bus condition decoded from a recording, see [Recorder::conditions]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Condition {
+    /// SDA fell while SCL was high; a repeated START if no STOP preceded it
+    Start,
+    /// SDA rose while SCL was high
+    Stop,
+}
+
+/// Violation of a waveform invariant, see [Recorder::check_i2c]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Violation {
+    /// More events occurred than the recorder could hold
+    Overflow,
+    /// SDA changed in the same tick SCL rose, creating an unintended START
+    /// or STOP, at event `index`
+    SdaChangedWhileSclHigh {
+        /// Index of the offending event
+        index: usize,
+    },
+    /// SCL fell in the same tick as a START or STOP, at event `index`
+    NoHoldTime {
+        /// Index of the offending event
+        index: usize,
+    },
+    /// The recording does not end with both lines released
+    BusNotReleased,
+}
+
+/// Event log shared by mock pins and timer
+pub struct Recorder<const N: usize> {
+    events: [Event; N],
+    len: usize,
+    overflow: bool,
+    time: u32,
+    reads: [bool; N],
+    reads_len: usize,
+    reads_pos: usize,
+}
+
+impl<const N: usize> Recorder<N> {
+    /// Create an empty recorder
+    pub const fn new() -> Self {
+        Recorder {
+            events: [Event {
+                time: 0,
+                line: Line::Other(0),
+                level: false,
+            }; N],
+            len: 0,
+            overflow: false,
+            time: 0,
+            reads: [false; N],
+            reads_len: 0,
+            reads_pos: 0,
+        }
+    }
+
+    /// Recorded events, oldest first
+    pub fn events(&self) -> &[Event] {
+        &self.events[..self.len]
+    }
+
+    /// Timer ticks waited so far
+    pub fn time(&self) -> u32 {
+        self.time
+    }
+
+    /// Discard all events and queued reads and reset the time
+    pub fn clear(&mut self) {
+        *self = Self::new();
+    }
+
+    /// Queue levels to be returned by the next pin reads, in order
+    ///
+    /// Levels that do not fit the queue are dropped.
+    pub fn queue_reads(&mut self, levels: &[bool]) {
+        for level in levels {
+            if self.reads_len < N {
+                self.reads[self.reads_len] = *level;
+                self.reads_len += 1;
+            }
+        }
+    }
+
+    /// Check the recording against the I2C edge ordering rules
+    ///
+    /// Both lines are assumed high before the first event. A change of SDA
+    /// while SCL is high is a START (falling) or STOP (rising) and must come
+    /// at least one tick after SCL rose, and SCL must stay high for at least
+    /// one tick after it. Both lines must be released at the end.
+    pub fn check_i2c(&self) -> Result<(), Violation> {
+        if self.overflow {
+            return Err(Violation::Overflow);
+        }
+
+        let mut scl = true;
+        let mut sda = true;
+        let mut scl_rose_at = None;
+        let mut condition_at = None;
+
+        for (index, event) in self.events().iter().enumerate() {
+            match event.line {
+                Line::Scl if event.level != scl => {
+                    if !event.level && condition_at == Some(event.time) {
+                        return Err(Violation::NoHoldTime { index });
+                    }
+                    scl = event.level;
+                    if scl {
+                        scl_rose_at = Some(event.time);
+                    }
+                }
+                Line::Sda if event.level != sda => {
+                    if scl {
+                        if scl_rose_at == Some(event.time) {
+                            return Err(Violation::SdaChangedWhileSclHigh { index });
+                        }
+                        condition_at = Some(event.time);
+                    }
+                    sda = event.level;
+                }
+                _ => {}
+            }
+        }
+
+        if scl && sda {
+            Ok(())
+        } else {
+            Err(Violation::BusNotReleased)
+        }
+    }
+
+    /// START and STOP conditions in the recording, with their event index
+    ///
+    /// Both lines are assumed high before the first event.
+    pub fn conditions(&self) -> impl Iterator<Item = (usize, Condition)> + '_ {
+        let mut scl = true;
+        let mut sda = true;
+        self.events()
+            .iter()
+            .enumerate()
+            .filter_map(move |(index, event)| match event.line {
+                Line::Scl => {
+                    scl = event.level;
+                    None
+                }
+                Line::Sda if event.level != sda => {
+                    sda = event.level;
+                    match (scl, sda) {
+                        (true, false) => Some((index, Condition::Start)),
+                        (true, true) => Some((index, Condition::Stop)),
+                        _ => None,
+                    }
+                }
+                _ => None,
+            })
+    }
+
+    fn record(&mut self, line: Line, level: bool) {
+        if self.len < N {
+            self.events[self.len] = Event {
+                time: self.time,
+                line,
+                level,
+            };
+            self.len += 1;
+        } else {
+            self.overflow = true;
+        }
+    }
+
+    fn next_read(&mut self) -> Option<bool> {
+        if self.reads_pos < self.reads_len {
+            self.reads_pos += 1;
+            Some(self.reads[self.reads_pos - 1])
+        } else {
+            None
+        }
+    }
+}
+
+impl<const N: usize> Default for Recorder<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Pin recording every level it is driven to
+pub struct MockPin<'a, const N: usize> {
+    recorder: &'a RefCell<Recorder<N>>,
+    line: Line,
+    level: bool,
+}
+
+impl<'a, const N: usize> MockPin<'a, N> {
+    /// Create a pin for `line`, initially high
+    pub fn new(recorder: &'a RefCell<Recorder<N>>, line: Line) -> Self {
+        MockPin {
+            recorder,
+            line,
+            level: true,
+        }
+    }
+}
+
+impl<const N: usize> OutputPin for MockPin<'_, N> {
+    type Error = Infallible;
+
+    fn set_low(&mut self) -> Result<(), Infallible> {
+        self.level = false;
+        self.recorder.borrow_mut().record(self.line, false);
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Infallible> {
+        self.level = true;
+        self.recorder.borrow_mut().record(self.line, true);
+        Ok(())
+    }
+}
+
+impl<const N: usize> InputPin for MockPin<'_, N> {
+    type Error = Infallible;
+
+    fn is_high(&self) -> Result<bool, Infallible> {
+        Ok(self.recorder.borrow_mut().next_read().unwrap_or(self.level))
+    }
+
+    fn is_low(&self) -> Result<bool, Infallible> {
+        self.is_high().map(|high| !high)
+    }
+}
+
+/// Timer advancing the recorder time by one tick per wait
+pub struct MockTimer<'a, const N: usize> {
+    recorder: &'a RefCell<Recorder<N>>,
+}
+
+impl<'a, const N: usize> MockTimer<'a, N> {
+    /// Create instance
+    pub fn new(recorder: &'a RefCell<Recorder<N>>) -> Self {
+        MockTimer { recorder }
+    }
+}
+
+impl<const N: usize> CountDown for MockTimer<'_, N> {
+    type Time = u32;
+
+    fn start<T>(&mut self, _count: T)
+    where
+        T: Into<u32>,
+    {
+    }
+
+    fn wait(&mut self) -> nb::Result<(), Void> {
+        self.recorder.borrow_mut().time += 1;
+        Ok(())
+    }
+}
+
+impl<const N: usize> Periodic for MockTimer<'_, N> {}
+
+/// Open-drain pin whose line is also pulled low by another device, for
+/// tests that need a slave to respond at particular ticks
+///
+/// Writes are recorded like those of a [MockPin]. Reads return high only if
+/// the pin was last driven high and `wave` is high at the current time.
+#[cfg(test)]
+pub(crate) struct WavePin<'a, const N: usize> {
+    pin: MockPin<'a, N>,
+    wave: &'a dyn Fn(u32) -> bool,
+}
+
+#[cfg(test)]
+impl<'a, const N: usize> WavePin<'a, N> {
+    pub(crate) fn new(
+        recorder: &'a RefCell<Recorder<N>>,
+        line: Line,
+        wave: &'a dyn Fn(u32) -> bool,
+    ) -> Self {
+        WavePin {
+            pin: MockPin::new(recorder, line),
+            wave,
+        }
+    }
+}
+
+#[cfg(test)]
+impl<const N: usize> OutputPin for WavePin<'_, N> {
+    type Error = Infallible;
+
+    fn set_low(&mut self) -> Result<(), Infallible> {
+        self.pin.set_low()
+    }
+
+    fn set_high(&mut self) -> Result<(), Infallible> {
+        self.pin.set_high()
+    }
+}
+
+#[cfg(test)]
+impl<const N: usize> InputPin for WavePin<'_, N> {
+    type Error = Infallible;
+
+    fn is_high(&self) -> Result<bool, Infallible> {
+        let time = self.pin.recorder.borrow().time();
+        Ok(self.pin.level && (self.wave)(time))
+    }
+
+    fn is_low(&self) -> Result<bool, Infallible> {
+        self.is_high().map(|high| !high)
+    }
+}
+
+/// [MockPin] failing one access after a given number of accesses, for tests
+/// of the error paths
+///
+/// Every set and read counts down `fail_in`; the access that finds it at
+/// zero fails with `Err(())` without changing or reading the pin, and
+/// disarms it.
+#[cfg(test)]
+pub(crate) struct FlakyPin<'a, const N: usize> {
+    pin: MockPin<'a, N>,
+    fail_in: &'a core::cell::Cell<Option<u32>>,
+}
+
+#[cfg(test)]
+impl<'a, const N: usize> FlakyPin<'a, N> {
+    pub(crate) fn new(
+        recorder: &'a RefCell<Recorder<N>>,
+        line: Line,
+        fail_in: &'a core::cell::Cell<Option<u32>>,
+    ) -> Self {
+        FlakyPin {
+            pin: MockPin::new(recorder, line),
+            fail_in,
+        }
+    }
+
+    fn access(&self) -> Result<(), ()> {
+        match self.fail_in.get() {
+            Some(0) => {
+                self.fail_in.set(None);
+                Err(())
+            }
+            Some(n) => {
+                self.fail_in.set(Some(n - 1));
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+impl<const N: usize> OutputPin for FlakyPin<'_, N> {
+    type Error = ();
+
+    fn set_low(&mut self) -> Result<(), ()> {
+        self.access()?;
+        let Ok(()) = self.pin.set_low();
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), ()> {
+        self.access()?;
+        let Ok(()) = self.pin.set_high();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+impl<const N: usize> InputPin for FlakyPin<'_, N> {
+    type Error = ();
+
+    fn is_high(&self) -> Result<bool, ()> {
+        self.access()?;
+        let Ok(high) = self.pin.is_high();
+        Ok(high)
+    }
+
+    fn is_low(&self) -> Result<bool, ()> {
+        self.is_high().map(|high| !high)
+    }
+}
@@ -5,23 +5,244 @@
 //! - Output GPIO pin for transmission (TX)
 //! - Input GPIO pin for reception (RX)
 //!
-//! The timer must be configured to twice the desired communication frequency.
+//! The driver needs to know how many timer ticks make one bit, see
+//! [TimerRate]. [Serial::new] expects the timer to run at the baud rate
+//! ([TimerRate::OneX]), so each bit lasts one tick. A timer running at twice
+//! the baud rate must be declared with [Serial::new_with_timer_rate] and
+//! [TimerRate::TwoX], otherwise the data is sent at twice the baud rate.
+//!
+//! Half-bit timing, needed for 1.5 stop bits, for sampling received bits in
+//! their middle and for rejecting glitches on RX that are shorter than half a
+//! bit instead of taking them for a start bit, requires [TimerRate::TwoX].
+//! Plain sending and receiving works at either rate.
+//!
+//! For noisy lines, [Serial::set_samples_per_bit] reads every received bit
+//! several times, one tick apart, and takes the majority. The timer must then
+//! run that many times faster, e.g. at six times the baud rate for three
+//! samples with [TimerRate::TwoX], and every bit period lasts as many more
+//! ticks.
+//!
+//! A frame that fails to send because of a pin error is abandoned with TX
+//! driven back high, ignoring any further pin error, and the next frame
+//! is preceded by the idle time set with
+//! [Serial::set_idle_before_tx_bits] like the first one.
+//!
+//! With the `embedded-io` feature, [Serial] also implements the
+//! `embedded_io::Read` and `embedded_io::Write` byte stream traits.
 //!
 
+use embedded_hal::blocking::delay::DelayUs;
 use embedded_hal::digital::v2::{InputPin, OutputPin};
 use embedded_hal::serial;
 use embedded_hal::timer::{CountDown, Periodic};
 use nb::block;
 
+use crate::crc::xor_sum;
+use crate::timing::{close_to, half_period_wait, DelayTimer};
+
 /// Serial communication error type
 #[derive(Debug)]
 pub enum Error<E> {
     /// Bus error
     Bus(E),
+    /// Received frame checksum does not match
+    Checksum,
+    /// Frame does not fit the buffer or exceeds the maximum length
+    Overflow,
+    /// Break received, RX was held low for at least the break threshold
+    Break,
+}
+
+impl<E> core::fmt::Display for Error<E>
+where
+    E: core::fmt::Debug,
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Error::Bus(e) => crate::error::fmt_bus_error(f, None, e),
+            Error::Checksum => f.write_str("checksum mismatch"),
+            Error::Overflow => f.write_str("frame too long"),
+            Error::Break => f.write_str("break"),
+        }
+    }
+}
+
+#[cfg(feature = "core-error")]
+impl<E> core::error::Error for Error<E>
+where
+    E: core::error::Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            Error::Bus(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// Start-of-frame byte of [Serial::send_frame] and [Serial::recv_frame]
+pub const FRAME_START: u8 = 0x7E;
+
+/// XOR checksum of a frame's length byte and payload
+fn frame_checksum(payload: &[u8]) -> u8 {
+    payload.len() as u8 ^ xor_sum(payload)
+}
+
+/// Number of words queued for transmission with deferred flush
+const TX_BUFFER_LEN: usize = 16;
+
+/// Default break detection threshold in bit periods
+const DEFAULT_BREAK_THRESHOLD_BITS: u32 = 11;
+
+/// Timer frequency relative to the baud rate
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TimerRate {
+    /// One tick per bit
+    OneX,
+    /// Two ticks per bit, which allows half-bit timing
+    TwoX,
+}
+
+impl Default for TimerRate {
+    /// Default timer rate: one tick per bit
+    fn default() -> Self {
+        TimerRate::OneX
+    }
+}
+
+/// Number of stop bits
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum StopBits {
+    /// One stop bit
+    One,
+    /// One and a half stop bits
+    ///
+    /// Requires [TimerRate::TwoX], with [TimerRate::OneX] this is rounded up
+    /// to two stop bits.
+    OnePointFive,
+    /// Two stop bits
+    Two,
+}
+
+impl Default for StopBits {
+    /// Default stop bits: one
+    fn default() -> Self {
+        StopBits::One
+    }
+}
+
+/// Number of data bits per frame
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DataBits {
+    /// Five data bits
+    Five,
+    /// Six data bits
+    Six,
+    /// Seven data bits
+    Seven,
+    /// Eight data bits
+    Eight,
+    /// Nine data bits
+    ///
+    /// The ninth bit is only transferred by [Serial::write_word] and
+    /// [Serial::read_word], the `u8` traits drop it.
+    Nine,
+}
+
+impl DataBits {
+    fn count(self) -> u32 {
+        match self {
+            DataBits::Five => 5,
+            DataBits::Six => 6,
+            DataBits::Seven => 7,
+            DataBits::Eight => 8,
+            DataBits::Nine => 9,
+        }
+    }
+}
+
+impl Default for DataBits {
+    /// Default data bits: eight
+    fn default() -> Self {
+        DataBits::Eight
+    }
+}
+
+/// Number of samples taken of each received bit
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SamplesPerBit {
+    /// One sample
+    One,
+    /// Three samples, one tick apart
+    Three,
+    /// Five samples, one tick apart
+    Five,
+}
+
+impl SamplesPerBit {
+    fn count(self) -> u32 {
+        match self {
+            SamplesPerBit::One => 1,
+            SamplesPerBit::Three => 3,
+            SamplesPerBit::Five => 5,
+        }
+    }
+}
+
+impl Default for SamplesPerBit {
+    /// Default samples per bit: one
+    fn default() -> Self {
+        SamplesPerBit::One
+    }
+}
+
+/// Fixed-size FIFO of received words
+struct RingBuffer<const N: usize> {
+    buf: [u16; N],
+    head: usize,
+    len: usize,
+}
+
+impl<const N: usize> RingBuffer<N> {
+    const fn new() -> Self {
+        RingBuffer {
+            buf: [0; N],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn is_full(&self) -> bool {
+        self.len == N
+    }
+
+    fn push(&mut self, word: u16) {
+        if !self.is_full() {
+            self.buf[(self.head + self.len) % N] = word;
+            self.len += 1;
+        }
+    }
+
+    fn pop(&mut self) -> Option<u16> {
+        if self.is_empty() {
+            return None;
+        }
+        let word = self.buf[self.head];
+        self.head = (self.head + 1) % N;
+        self.len -= 1;
+        Some(word)
+    }
 }
 
 /// Bit banging serial communication (USART) device
-pub struct Serial<TX, RX, Timer>
+///
+/// `N` is the size of the optional receive buffer, see
+/// [with_rx_buffer](Serial::with_rx_buffer).
+pub struct Serial<TX, RX, Timer, const N: usize = 0>
 where
     TX: OutputPin,
     RX: InputPin,
@@ -29,7 +250,20 @@ where
 {
     tx: TX,
     rx: RX,
-    timer: Timer,
+    timer: Option<Timer>,
+    rx_buffer: RingBuffer<N>,
+    timer_rate: TimerRate,
+    stop_bits: StopBits,
+    data_bits: DataBits,
+    samples_per_bit: SamplesPerBit,
+    break_threshold_bits: u32,
+    tx_buffer: RingBuffer<TX_BUFFER_LEN>,
+    deferred_flush: bool,
+    idle_before_tx_bits: u32,
+    tx_idle: bool,
+    wake_break_bits: u32,
+    wake_sync: &'static [u8],
+    configured_hz: u32,
 }
 
 impl<TX, RX, Timer, E> Serial<TX, RX, Timer>
@@ -38,49 +272,797 @@ where
     RX: InputPin<Error = E>,
     Timer: CountDown + Periodic,
 {
-    /// Create instance
+    /// Create instance for a timer running at the baud rate
+    ///
+    /// Equivalent to [new_with_timer_rate](Self::new_with_timer_rate) with
+    /// [TimerRate::OneX].
     pub fn new(tx: TX, rx: RX, timer: Timer) -> Self {
-        Serial { tx, rx, timer }
+        Serial {
+            tx,
+            rx,
+            timer: Some(timer),
+            rx_buffer: RingBuffer::new(),
+            timer_rate: TimerRate::default(),
+            stop_bits: StopBits::default(),
+            data_bits: DataBits::default(),
+            samples_per_bit: SamplesPerBit::default(),
+            break_threshold_bits: DEFAULT_BREAK_THRESHOLD_BITS,
+            tx_buffer: RingBuffer::new(),
+            deferred_flush: false,
+            idle_before_tx_bits: 0,
+            tx_idle: true,
+            wake_break_bits: 0,
+            wake_sync: &[],
+            configured_hz: 0,
+        }
     }
 
-    #[inline]
-    fn wait_for_timer(&mut self) {
-        block!(self.timer.wait()).ok();
+    /// Create instance for a timer running at `rate` times the baud rate
+    pub fn new_with_timer_rate(tx: TX, rx: RX, timer: Timer, rate: TimerRate) -> Self {
+        let mut serial = Self::new(tx, rx, timer);
+        serial.timer_rate = rate;
+        serial
+    }
+
+    /// Create instance, deriving the [TimerRate] from the timer frequency
+    ///
+    /// `baud` is the intended baud rate and `timer_hz` the frequency the
+    /// timer was started with. A timer at the baud rate selects
+    /// [TimerRate::OneX], one at twice the baud rate [TimerRate::TwoX].
+    ///
+    /// # Panics
+    ///
+    /// In debug builds, if `timer_hz` is not within 10 % of either. Release
+    /// builds then fall back to [TimerRate::OneX].
+    pub fn new_checked(tx: TX, rx: RX, timer: Timer, baud: u32, timer_hz: u32) -> Self {
+        let twice = close_to(timer_hz, baud.saturating_mul(2));
+        debug_assert!(
+            twice || close_to(timer_hz, baud),
+            "timer must run at once or twice the baud rate"
+        );
+        let rate = if twice {
+            TimerRate::TwoX
+        } else {
+            TimerRate::OneX
+        };
+        let mut serial = Self::new_with_timer_rate(tx, rx, timer, rate);
+        serial.configured_hz = timer_hz;
+        serial
+    }
+
+    /// Add a receive buffer of `M` bytes
+    ///
+    /// Whenever a byte is read and another start bit follows within one bit
+    /// period of its stop bit, the following bytes are captured into the
+    /// buffer until it is full or the line stays idle for longer. Subsequent
+    /// reads drain the buffer before sampling the line again, so bytes
+    /// arriving back-to-back are not dropped when the caller polls slowly.
+    pub fn with_rx_buffer<const M: usize>(self) -> Serial<TX, RX, Timer, M> {
+        Serial {
+            tx: self.tx,
+            rx: self.rx,
+            timer: self.timer,
+            rx_buffer: RingBuffer::new(),
+            timer_rate: self.timer_rate,
+            stop_bits: self.stop_bits,
+            data_bits: self.data_bits,
+            samples_per_bit: self.samples_per_bit,
+            break_threshold_bits: self.break_threshold_bits,
+            tx_buffer: self.tx_buffer,
+            deferred_flush: self.deferred_flush,
+            idle_before_tx_bits: self.idle_before_tx_bits,
+            tx_idle: self.tx_idle,
+            wake_break_bits: self.wake_break_bits,
+            wake_sync: self.wake_sync,
+            configured_hz: self.configured_hz,
+        }
+    }
+}
+
+impl<TX, RX, D, E> Serial<TX, RX, DelayTimer<D>>
+where
+    TX: OutputPin<Error = E>,
+    RX: InputPin<Error = E>,
+    D: DelayUs<u32>,
+{
+    /// Create instance clocked by a blocking delay
+    ///
+    /// `tick_us` is the duration of one timer tick in microseconds, i.e. one
+    /// bit period with the default [TimerRate]. See [DelayTimer].
+    pub fn new_with_delay(tx: TX, rx: RX, delay: D, tick_us: u32) -> Self {
+        let mut serial = Self::new(tx, rx, DelayTimer::new(delay, tick_us));
+        serial.configured_hz = 1_000_000u32.checked_div(tick_us).unwrap_or(0);
+        serial
+    }
+}
+
+/// Builder for [Serial] with named setters
+///
+/// TX, RX and the timer are required; [build](SerialBuilder::build) is only
+/// available once all of them have been set, so a missing pin is a compile
+/// error. The remaining options default to the values used by [Serial::new].
+///
+/// ```ignore
+/// let serial = SerialBuilder::new()
+///     .tx(tx)
+///     .rx(rx)
+///     .timer(timer)
+///     .timer_rate(TimerRate::TwoX)
+///     .stop_bits(StopBits::Two)
+///     .build();
+/// ```
+pub struct SerialBuilder<TX = (), RX = (), Timer = ()> {
+    tx: TX,
+    rx: RX,
+    timer: Timer,
+    timer_rate: TimerRate,
+    stop_bits: StopBits,
+    data_bits: DataBits,
+    samples_per_bit: SamplesPerBit,
+    break_threshold_bits: u32,
+    idle_before_tx_bits: u32,
+}
+
+impl SerialBuilder {
+    /// Create a builder without pins and timer
+    pub fn new() -> Self {
+        SerialBuilder {
+            tx: (),
+            rx: (),
+            timer: (),
+            timer_rate: TimerRate::default(),
+            stop_bits: StopBits::default(),
+            data_bits: DataBits::default(),
+            samples_per_bit: SamplesPerBit::default(),
+            break_threshold_bits: DEFAULT_BREAK_THRESHOLD_BITS,
+            idle_before_tx_bits: 0,
+        }
+    }
+}
+
+impl Default for SerialBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<TX, RX, Timer> SerialBuilder<TX, RX, Timer> {
+    /// Set the TX pin
+    pub fn tx<P>(self, tx: P) -> SerialBuilder<P, RX, Timer> {
+        SerialBuilder {
+            tx,
+            rx: self.rx,
+            timer: self.timer,
+            timer_rate: self.timer_rate,
+            stop_bits: self.stop_bits,
+            data_bits: self.data_bits,
+            samples_per_bit: self.samples_per_bit,
+            break_threshold_bits: self.break_threshold_bits,
+            idle_before_tx_bits: self.idle_before_tx_bits,
+        }
+    }
+
+    /// Set the RX pin
+    pub fn rx<P>(self, rx: P) -> SerialBuilder<TX, P, Timer> {
+        SerialBuilder {
+            tx: self.tx,
+            rx,
+            timer: self.timer,
+            timer_rate: self.timer_rate,
+            stop_bits: self.stop_bits,
+            data_bits: self.data_bits,
+            samples_per_bit: self.samples_per_bit,
+            break_threshold_bits: self.break_threshold_bits,
+            idle_before_tx_bits: self.idle_before_tx_bits,
+        }
+    }
+
+    /// Set the timer
+    pub fn timer<T>(self, timer: T) -> SerialBuilder<TX, RX, T> {
+        SerialBuilder {
+            tx: self.tx,
+            rx: self.rx,
+            timer,
+            timer_rate: self.timer_rate,
+            stop_bits: self.stop_bits,
+            data_bits: self.data_bits,
+            samples_per_bit: self.samples_per_bit,
+            break_threshold_bits: self.break_threshold_bits,
+            idle_before_tx_bits: self.idle_before_tx_bits,
+        }
+    }
+
+    /// Set the timer frequency relative to the baud rate, see
+    /// [Serial::set_timer_rate]
+    pub fn timer_rate(mut self, rate: TimerRate) -> Self {
+        self.timer_rate = rate;
+        self
+    }
+
+    /// Set the number of transmitted stop bits
+    pub fn stop_bits(mut self, stop_bits: StopBits) -> Self {
+        self.stop_bits = stop_bits;
+        self
+    }
+
+    /// Set the number of data bits per frame
+    pub fn data_bits(mut self, data_bits: DataBits) -> Self {
+        self.data_bits = data_bits;
+        self
+    }
+
+    /// Set the number of samples per received bit, see
+    /// [Serial::set_samples_per_bit]
+    pub fn samples_per_bit(mut self, samples: SamplesPerBit) -> Self {
+        self.samples_per_bit = samples;
+        self
+    }
+
+    /// Set the break detection threshold, see
+    /// [Serial::set_break_threshold_bits]
+    pub fn break_threshold_bits(mut self, bits: u32) -> Self {
+        self.break_threshold_bits = bits;
+        self
+    }
+
+    /// Set the idle time before the first transmitted frame, see
+    /// [Serial::set_idle_before_tx_bits]
+    pub fn idle_before_tx_bits(mut self, bits: u32) -> Self {
+        self.idle_before_tx_bits = bits;
+        self
     }
 }
 
-impl<TX, RX, Timer, E> serial::Write<u8> for Serial<TX, RX, Timer>
+impl<TX, RX, Timer, E> SerialBuilder<TX, RX, Timer>
 where
     TX: OutputPin<Error = E>,
     RX: InputPin<Error = E>,
     Timer: CountDown + Periodic,
 {
-    type Error = crate::serial::Error<E>;
+    /// Create the [Serial] instance
+    ///
+    /// Use [with_rx_buffer](Serial::with_rx_buffer) on the result to add a
+    /// receive buffer.
+    pub fn build(self) -> Serial<TX, RX, Timer> {
+        let mut serial = Serial::new(self.tx, self.rx, self.timer);
+        serial.set_timer_rate(self.timer_rate);
+        serial.set_stop_bits(self.stop_bits);
+        serial.set_data_bits(self.data_bits);
+        serial.set_samples_per_bit(self.samples_per_bit);
+        serial.set_break_threshold_bits(self.break_threshold_bits);
+        serial.set_idle_before_tx_bits(self.idle_before_tx_bits);
+        serial
+    }
+}
 
-    fn write(&mut self, byte: u8) -> nb::Result<(), Self::Error> {
-        let mut data_out = byte;
+impl<TX, RX, Timer, E, const N: usize> Serial<TX, RX, Timer, N>
+where
+    TX: OutputPin<Error = E>,
+    RX: InputPin<Error = E>,
+    Timer: CountDown + Periodic,
+{
+    /// Allows for an access to the timer type.
+    /// This can be used to change the speed.
+    ///
+    /// In closure you get ownership of the timer
+    /// so you can destruct it and build it up again if necessary.
+    ///
+    /// # Example
+    ///
+    /// ```Rust
+    ///serial.access_timer(|mut timer| {
+    ///    timer.set_freq((2 * 9_600).hz());
+    ///    timer
+    ///});
+    ///```
+    ///
+    pub fn access_timer<F>(&mut self, f: F)
+    where
+        F: FnOnce(Timer) -> Timer,
+    {
+        if let Some(timer) = self.timer.take() {
+            self.timer = Some(f(timer));
+        }
+    }
+
+    /// Replace the timer, dropping the current one.
+    ///
+    /// Use [access_timer](Self::access_timer) to reconfigure the existing
+    /// timer instead.
+    pub fn set_timer(&mut self, timer: Timer) {
+        self.timer = Some(timer);
+    }
+
+    /// Record the timer frequency in Hz for [protocol_hz](Self::protocol_hz)
+    pub fn set_configured_hz(&mut self, hz: u32) {
+        self.configured_hz = hz;
+    }
+
+    /// Baud rate in Hz implied by the configured timer frequency, the
+    /// [TimerRate] and the [SamplesPerBit]
+    ///
+    /// Returns 0 if no timer frequency was recorded.
+    pub fn protocol_hz(&self) -> u32 {
+        self.configured_hz / self.half_bit_ticks(2)
+    }
+
+    /// Set the timer frequency relative to the baud rate
+    ///
+    /// With [TimerRate::TwoX] each bit lasts two timer ticks, which allows
+    /// sampling received bits in their middle and half-bit stop periods.
+    pub fn set_timer_rate(&mut self, rate: TimerRate) {
+        self.timer_rate = rate;
+    }
+
+    /// Set the number of transmitted stop bits
+    pub fn set_stop_bits(&mut self, stop_bits: StopBits) {
+        self.stop_bits = stop_bits;
+    }
+
+    /// Set the number of data bits per frame
+    ///
+    /// Transmitted values are masked to the selected width and received
+    /// values are right-aligned. Data bits go least significant first in
+    /// both directions, the standard UART order. Defaults to
+    /// [DataBits::Eight].
+    pub fn set_data_bits(&mut self, data_bits: DataBits) {
+        self.data_bits = data_bits;
+    }
+
+    /// Set the number of samples taken of each received bit
+    ///
+    /// Every bit is read this many times, one timer tick apart, and the
+    /// majority wins, so a glitch shorter than a tick does not flip the bit.
+    /// With [TimerRate::TwoX] the samples are centred on the middle of the
+    /// bit, with [TimerRate::OneX] they start at its beginning. The timer has
+    /// to run this many times faster than the [TimerRate] alone requires,
+    /// for sending as well. Defaults to [SamplesPerBit::One].
+    pub fn set_samples_per_bit(&mut self, samples: SamplesPerBit) {
+        self.samples_per_bit = samples;
+    }
+
+    /// Set the number of bit periods RX must stay low to be taken as a break
+    ///
+    /// The count includes the start bit. A received frame whose data bits
+    /// and stop bit are all low is followed until RX rises: if it stays low
+    /// for at least `bits` bit periods, the read returns `Err(Break)` once the
+    /// line is released, otherwise the zero frame is returned as usual.
+    /// Defaults to 11, e.g. LIN slaves would use 11 for the 13-bit break.
+    pub fn set_break_threshold_bits(&mut self, bits: u32) {
+        self.break_threshold_bits = bits;
+    }
+
+    /// Set the number of bit periods TX is held high before transmitting
+    /// after an idle period
+    ///
+    /// Gives receivers that need a guaranteed mark time, such as software
+    /// UARTs or optoisolated links, a chance to settle before the first start
+    /// bit. The driver cannot see time passing between calls, so the line
+    /// counts as idle after construction and after each received frame.
+    /// Defaults to 0.
+    pub fn set_idle_before_tx_bits(&mut self, bits: u32) {
+        self.idle_before_tx_bits = bits;
+    }
+
+    /// Set the pattern sent by [wake](Self::wake)
+    ///
+    /// TX is held low for `break_bits` bit periods, followed by a one bit
+    /// break delimiter, and then the `sync` bytes are sent, e.g. 13 and
+    /// `&[0x55]` for a LIN style break and sync field, or 0 and a run of 0xFF
+    /// for receivers that lock on to a stream of idle frames. A zero
+    /// `break_bits` sends no break. Defaults to no break and no sync bytes,
+    /// which makes `wake` do nothing.
+    pub fn set_wake_pattern(&mut self, break_bits: u32, sync: &'static [u8]) {
+        self.wake_break_bits = break_bits;
+        self.wake_sync = sync;
+    }
+
+    /// Send the wake-up pattern set with
+    /// [set_wake_pattern](Self::set_wake_pattern)
+    ///
+    /// Meant to be called once at startup, to prime receivers that need a
+    /// break or sync bytes before they lock on. Queued words are sent first.
+    pub fn wake(&mut self) -> Result<(), crate::serial::Error<E>> {
+        self.flush_tx()?;
+
+        if self.wake_break_bits > 0 {
+            self.tx.set_low().map_err(Error::Bus)?;
+            self.wait_half_bits(2 * self.wake_break_bits);
+            self.tx.set_high().map_err(Error::Bus)?; // break delimiter
+            self.wait_half_bits(2);
+            self.tx_idle = false;
+        }
+
+        for byte in self.wake_sync {
+            self.transmit_word(*byte as u16)?;
+        }
+        Ok(())
+    }
+
+    /// Queue written data until flushed
+    ///
+    /// When enabled, `write` stores up to 16 words in an internal buffer
+    /// instead of sending them, and `flush` clocks out the whole buffer
+    /// back-to-back without gaps between frames. A write to a full buffer
+    /// sends the buffer first. Words still queued when this is disabled are
+    /// sent by the next flush.
+    pub fn set_deferred_flush(&mut self, enabled: bool) {
+        self.deferred_flush = enabled;
+    }
+
+    /// Transmit one frame of up to nine data bits
+    ///
+    /// Unlike the `u8` [Write](serial::Write) implementation this can send
+    /// the ninth bit with [DataBits::Nine].
+    pub fn write_word(&mut self, word: u16) -> nb::Result<(), crate::serial::Error<E>> {
+        if self.deferred_flush {
+            if self.tx_buffer.is_full() {
+                self.flush_tx()?;
+            }
+            self.tx_buffer.push(word);
+            return Ok(());
+        }
+
+        self.transmit_word(word)?;
+        Ok(())
+    }
+
+    /// Send all queued words
+    fn flush_tx(&mut self) -> Result<(), crate::serial::Error<E>> {
+        while let Some(word) = self.tx_buffer.pop() {
+            self.transmit_word(word)?;
+        }
+        Ok(())
+    }
+
+    fn transmit_word(&mut self, word: u16) -> Result<(), crate::serial::Error<E>> {
+        let result = self.transmit_frame(word);
+        if result.is_err() {
+            self.tx.set_high().ok();
+            self.tx_idle = true;
+        }
+        result
+    }
+
+    fn transmit_frame(&mut self, word: u16) -> Result<(), crate::serial::Error<E>> {
+        let mut data_out = word;
+        if self.tx_idle {
+            self.tx_idle = false;
+            if self.idle_before_tx_bits > 0 {
+                self.tx.set_high().map_err(Error::Bus)?; // mark idle
+                for _bit in 0..self.idle_before_tx_bits {
+                    self.wait_half_bits(2);
+                }
+            }
+        }
         self.tx.set_low().map_err(Error::Bus)?; // start bit
-        self.wait_for_timer();
-        for _bit in 0..8 {
+        self.wait_half_bits(2);
+        for _bit in 0..self.data_bits.count() {
             if data_out & 1 == 1 {
                 self.tx.set_high().map_err(Error::Bus)?;
             } else {
                 self.tx.set_low().map_err(Error::Bus)?;
             }
             data_out >>= 1;
-            self.wait_for_timer();
+            self.wait_half_bits(2);
         }
         self.tx.set_high().map_err(Error::Bus)?; // stop bit
-        self.wait_for_timer();
+        match self.stop_bits {
+            StopBits::One => self.wait_half_bits(2),
+            StopBits::OnePointFive => self.wait_half_bits(3),
+            StopBits::Two => self.wait_half_bits(4),
+        }
+        Ok(())
+    }
+
+    /// Check whether a read is likely to return data soon
+    ///
+    /// Returns `true` if the receive buffer holds words or RX reads low, a
+    /// potential start bit. RX is sampled once and nothing is consumed, so a
+    /// following read still sees the start bit if it is called within half
+    /// a bit period.
+    pub fn rx_pending(&mut self) -> Result<bool, crate::serial::Error<E>> {
+        if !self.rx_buffer.is_empty() {
+            return Ok(true);
+        }
+        self.rx.is_low().map_err(Error::Bus)
+    }
+
+    /// Receive one frame of up to nine data bits
+    ///
+    /// Unlike the `u8` [Read](serial::Read) implementation this returns the
+    /// ninth bit with [DataBits::Nine].
+    ///
+    /// With a receive buffer, RX is then polled for up to one bit period
+    /// after the stop bit, and a frame starting within that window is
+    /// captured into the buffer, see [with_rx_buffer](Serial::with_rx_buffer).
+    pub fn read_word(&mut self) -> nb::Result<u16, crate::serial::Error<E>> {
+        if let Some(word) = self.rx_buffer.pop() {
+            return Ok(word);
+        }
+
+        let word = self.receive_word()?;
+
+        // capture words following back-to-back
+        while !self.rx_buffer.is_full() && self.start_bit_follows()? {
+            let next = self.receive_word()?;
+            self.rx_buffer.push(next);
+        }
+
+        Ok(word)
+    }
+
+    /// Read one byte and immediately write it back out
+    ///
+    /// Useful as a quick end-to-end link test or for simple echo servers.
+    /// Returns the byte that was echoed.
+    pub fn echo_once(&mut self) -> nb::Result<u8, crate::serial::Error<E>> {
+        let byte = serial::Read::read(self)?;
+        serial::Write::write(self, byte)?;
+        Ok(byte)
+    }
+
+    /// Transmit a byte, then hold TX idle for `gap_bit_times` extra bit
+    /// periods
+    ///
+    /// The gap gives slow software receivers time to resynchronize before
+    /// the next start bit. Words queued with deferred flush are sent first,
+    /// and the byte itself is never queued.
+    pub fn write_byte_gap(
+        &mut self,
+        byte: u8,
+        gap_bit_times: u32,
+    ) -> Result<(), crate::serial::Error<E>> {
+        self.flush_tx()?;
+        self.transmit_word(byte as u16)?;
+        for _ in 0..gap_bit_times {
+            self.wait_half_bits(2);
+        }
         Ok(())
     }
 
+    /// Discard received data until RX has been idle for `idle_bit_times`
+    ///
+    /// Buffered words are dropped, and RX is checked once per bit period;
+    /// whenever it is low, the frame is received and discarded and the idle
+    /// count starts over. This gives request/response protocols a clean
+    /// starting point.
+    pub fn drain(&mut self, idle_bit_times: u32) -> Result<(), crate::serial::Error<E>> {
+        while self.rx_buffer.pop().is_some() {}
+
+        let mut idle = 0;
+        while idle < idle_bit_times {
+            if self.rx.is_low().map_err(Error::Bus)? {
+                self.receive_word()?;
+                idle = 0;
+            } else {
+                self.wait_half_bits(2);
+                idle += 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// Drive TX with an exact sequence of levels, one bit period each
+    ///
+    /// No start, stop or parity bits are added, so this can generate test
+    /// waveforms or non-UART framing at the configured bit rate. TX is left
+    /// at the level of the last bit.
+    pub fn write_raw_bits(&mut self, bits: &[bool]) -> Result<(), crate::serial::Error<E>> {
+        for bit in bits {
+            if *bit {
+                self.tx.set_high().map_err(Error::Bus)?;
+            } else {
+                self.tx.set_low().map_err(Error::Bus)?;
+            }
+            self.wait_half_bits(2);
+        }
+        Ok(())
+    }
+
+    /// Send `payload` as a checksummed frame
+    ///
+    /// The frame consists of [FRAME_START], a length byte, the payload and an
+    /// XOR checksum over the length byte and the payload. Payloads longer
+    /// than 255 bytes are rejected with `Err(Overflow)`.
+    pub fn send_frame(&mut self, payload: &[u8]) -> Result<(), crate::serial::Error<E>> {
+        let len = u8::try_from(payload.len()).map_err(|_| Error::Overflow)?;
+
+        block!(serial::Write::write(self, FRAME_START))?;
+        block!(serial::Write::write(self, len))?;
+        for byte in payload {
+            block!(serial::Write::write(self, *byte))?;
+        }
+        block!(serial::Write::write(self, frame_checksum(payload)))
+    }
+
+    /// Receive a frame sent by [send_frame](Self::send_frame)
+    ///
+    /// Bytes before [FRAME_START] are discarded. The payload is stored in
+    /// `buf` and its length returned. Returns `Err(Overflow)` right after the
+    /// length byte if the payload does not fit `buf`, and `Err(Checksum)` if
+    /// the checksum does not match.
+    pub fn recv_frame(&mut self, buf: &mut [u8]) -> Result<usize, crate::serial::Error<E>> {
+        while block!(serial::Read::read(self))? != FRAME_START {}
+
+        let len = block!(serial::Read::read(self))? as usize;
+        let payload = buf.get_mut(..len).ok_or(Error::Overflow)?;
+        for byte in payload.iter_mut() {
+            *byte = block!(serial::Read::read(self))?;
+        }
+
+        if block!(serial::Read::read(self))? != frame_checksum(payload) {
+            return Err(Error::Checksum);
+        }
+        Ok(len)
+    }
+
+    fn receive_word(&mut self) -> Result<u16, crate::serial::Error<E>> {
+        let mut data_in = 0;
+        self.tx_idle = true;
+        // wait for start bit
+        loop {
+            while self.rx.is_high().map_err(Error::Bus)? {}
+            match self.timer_rate {
+                // sample at the start of each bit
+                TimerRate::OneX => {
+                    self.wait_half_bits(2);
+                    break;
+                }
+                // sample in the middle of each bit, after confirming the start
+                // bit is still low half a bit later to reject glitches
+                TimerRate::TwoX => {
+                    self.wait_half_bits(1);
+                    if self.rx.is_low().map_err(Error::Bus)? {
+                        // start the samples early to centre them
+                        let lead_ticks = (self.samples_per_bit.count() - 1) / 2;
+                        self.wait_ticks(self.half_bit_ticks(2) - lead_ticks);
+                        break;
+                    }
+                }
+            }
+        }
+        let sample_ticks = self.samples_per_bit.count() - 1;
+        // the first data bit on the line is the least significant
+        for bit in 0..self.data_bits.count() {
+            if self.sample_rx()? {
+                data_in |= 1 << bit;
+            }
+            self.wait_ticks(self.half_bit_ticks(2) - sample_ticks);
+        }
+        // a break holds RX low through the stop bit and beyond
+        if data_in == 0 && self.rx.is_low().map_err(Error::Bus)? {
+            let mut low_bits = self.data_bits.count() + 2;
+            while self.rx.is_low().map_err(Error::Bus)? {
+                if low_bits >= self.break_threshold_bits {
+                    while self.rx.is_low().map_err(Error::Bus)? {}
+                    return Err(Error::Break);
+                }
+                self.wait_half_bits(2);
+                low_bits += 1;
+            }
+        }
+        // wait for stop bit
+        self.wait_half_bits(1);
+        Ok(data_in)
+    }
+
+    /// Poll RX for a start bit for up to one bit period
+    ///
+    /// The window is counted in timer ticks from the end of the previous
+    /// frame, so a transmitter may leave up to one bit of idle time between
+    /// frames. RX is polled continuously while the timer runs, so a
+    /// start bit is found without waiting for the end of a tick.
+    fn start_bit_follows(&mut self) -> Result<bool, crate::serial::Error<E>> {
+        let window = self.half_bit_ticks(2);
+        let mut ticks = 0;
+        loop {
+            if self.rx.is_low().map_err(Error::Bus)? {
+                return Ok(true);
+            }
+            if ticks >= window {
+                return Ok(false);
+            }
+            match self.timer.as_mut() {
+                Some(timer) => {
+                    if timer.wait().is_ok() {
+                        ticks += 1;
+                    }
+                }
+                None => return Ok(false),
+            }
+        }
+    }
+
+    /// Read RX once per tick for each sample of a bit and take the majority
+    fn sample_rx(&mut self) -> Result<bool, crate::serial::Error<E>> {
+        let count = self.samples_per_bit.count();
+        let mut high = 0;
+        for sample in 0..count {
+            if sample > 0 {
+                self.wait_for_timer();
+            }
+            if self.rx.is_high().map_err(Error::Bus)? {
+                high += 1;
+            }
+        }
+        Ok(high * 2 > count)
+    }
+
+    /// Number of ticks in `half_bits` half bit periods, rounded up to whole
+    /// bit periods with [TimerRate::OneX].
+    fn half_bit_ticks(&self, half_bits: u32) -> u32 {
+        let ticks = match self.timer_rate {
+            TimerRate::OneX => half_bits.div_ceil(2),
+            TimerRate::TwoX => half_bits,
+        };
+        ticks * self.samples_per_bit.count()
+    }
+
+    /// Wait for `half_bits` half bit periods, rounded up to whole ticks.
+    fn wait_half_bits(&mut self, half_bits: u32) {
+        self.wait_ticks(self.half_bit_ticks(half_bits));
+    }
+
+    #[inline]
+    fn wait_ticks(&mut self, ticks: u32) {
+        for _ in 0..ticks {
+            self.wait_for_timer();
+        }
+    }
+
+    #[inline]
+    fn wait_for_timer(&mut self) {
+        if let Some(timer) = self.timer.as_mut() {
+            half_period_wait(timer);
+        }
+    }
+}
+
+/// Shows the frame format and buffering configuration; pins and timer are
+/// omitted
+impl<TX, RX, Timer, const N: usize> core::fmt::Debug for Serial<TX, RX, Timer, N>
+where
+    TX: OutputPin,
+    RX: InputPin,
+    Timer: CountDown + Periodic,
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Serial")
+            .field("timer_rate", &self.timer_rate)
+            .field("data_bits", &self.data_bits)
+            .field("samples_per_bit", &self.samples_per_bit)
+            .field("stop_bits", &self.stop_bits)
+            .field("break_threshold_bits", &self.break_threshold_bits)
+            .field("idle_before_tx_bits", &self.idle_before_tx_bits)
+            .field("wake_break_bits", &self.wake_break_bits)
+            .field("wake_sync", &self.wake_sync)
+            .field("configured_hz", &self.configured_hz)
+            .field("rx_buffer_len", &N)
+            .field("deferred_flush", &self.deferred_flush)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<TX, RX, Timer, E, const N: usize> serial::Write<u8> for Serial<TX, RX, Timer, N>
+where
+    TX: OutputPin<Error = E>,
+    RX: InputPin<Error = E>,
+    Timer: CountDown + Periodic,
+{
+    type Error = crate::serial::Error<E>;
+
+    fn write(&mut self, byte: u8) -> nb::Result<(), Self::Error> {
+        self.write_word(u16::from(byte))
+    }
+
     fn flush(&mut self) -> nb::Result<(), Self::Error> {
+        self.flush_tx()?;
         Ok(())
     }
 }
 
-impl<TX, RX, Timer, E> serial::Read<u8> for Serial<TX, RX, Timer>
+impl<TX, RX, Timer, E, const N: usize> serial::Read<u8> for Serial<TX, RX, Timer, N>
 where
     TX: OutputPin<Error = E>,
     RX: InputPin<Error = E>,
@@ -89,19 +1071,562 @@ where
     type Error = crate::serial::Error<E>;
 
     fn read(&mut self) -> nb::Result<u8, Self::Error> {
-        let mut data_in = 0;
-        // wait for start bit
-        while self.rx.is_high().map_err(Error::Bus)? {}
-        self.wait_for_timer();
-        for _bit in 0..8 {
-            data_in <<= 1;
-            if self.rx.is_high().map_err(Error::Bus)? {
-                data_in |= 1
+        self.read_word().map(|word| word as u8)
+    }
+}
+
+#[cfg(feature = "embedded-io")]
+impl<E> embedded_io::Error for Error<E>
+where
+    E: core::fmt::Debug,
+{
+    fn kind(&self) -> embedded_io::ErrorKind {
+        match self {
+            Error::Checksum => embedded_io::ErrorKind::InvalidData,
+            _ => embedded_io::ErrorKind::Other,
+        }
+    }
+}
+
+#[cfg(feature = "embedded-io")]
+impl<TX, RX, Timer, E, const N: usize> embedded_io::ErrorType for Serial<TX, RX, Timer, N>
+where
+    TX: OutputPin<Error = E>,
+    RX: InputPin<Error = E>,
+    Timer: CountDown + Periodic,
+    E: core::fmt::Debug,
+{
+    type Error = crate::serial::Error<E>;
+}
+
+#[cfg(feature = "embedded-io")]
+impl<TX, RX, Timer, E, const N: usize> embedded_io::Read for Serial<TX, RX, Timer, N>
+where
+    TX: OutputPin<Error = E>,
+    RX: InputPin<Error = E>,
+    Timer: CountDown + Periodic,
+    E: core::fmt::Debug,
+{
+    /// Block until one byte has been received, then return it together with
+    /// any further bytes already in the receive buffer.
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let Some((first, rest)) = buf.split_first_mut() else {
+            return Ok(0);
+        };
+
+        *first = block!(serial::Read::read(self))?;
+
+        let mut count = 1;
+        for byte in rest {
+            match self.rx_buffer.pop() {
+                Some(received) => *byte = received as u8,
+                None => break,
             }
-            self.wait_for_timer();
+            count += 1;
         }
-        // wait for stop bit
-        self.wait_for_timer();
-        Ok(data_in)
+        Ok(count)
+    }
+}
+
+#[cfg(feature = "embedded-io")]
+impl<TX, RX, Timer, E, const N: usize> embedded_io::Write for Serial<TX, RX, Timer, N>
+where
+    TX: OutputPin<Error = E>,
+    RX: InputPin<Error = E>,
+    Timer: CountDown + Periodic,
+    E: core::fmt::Debug,
+{
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        for byte in buf {
+            block!(serial::Write::write(self, *byte))?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        block!(serial::Write::flush(self))
+    }
+}
+
+/// Half-duplex RS-485 serial device with a driver enable (DE) pin
+///
+/// DE is asserted (high) before the start bit of every transmitted byte and
+/// deasserted after its stop bits and a configurable turnaround delay, and is
+/// kept deasserted while receiving. Framing is the same as for [Serial].
+pub struct Rs485<TX, RX, Timer, DE, const N: usize = 0>
+where
+    TX: OutputPin,
+    RX: InputPin,
+    Timer: CountDown + Periodic,
+    DE: OutputPin,
+{
+    serial: Serial<TX, RX, Timer, N>,
+    de: DE,
+    turnaround_bits: u32,
+}
+
+impl<TX, RX, Timer, DE, E, const N: usize> Rs485<TX, RX, Timer, DE, N>
+where
+    TX: OutputPin<Error = E>,
+    RX: InputPin<Error = E>,
+    Timer: CountDown + Periodic,
+    DE: OutputPin<Error = E>,
+{
+    /// Create instance
+    ///
+    /// `turnaround_bits` is the number of bit periods DE stays asserted after
+    /// the stop bits of each byte.
+    pub fn new(serial: Serial<TX, RX, Timer, N>, mut de: DE, turnaround_bits: u32) -> Self {
+        de.set_low().unwrap_or(());
+        Rs485 {
+            serial,
+            de,
+            turnaround_bits,
+        }
+    }
+
+    /// Release the serial device and the DE pin
+    pub fn free(self) -> (Serial<TX, RX, Timer, N>, DE) {
+        (self.serial, self.de)
+    }
+}
+
+impl<TX, RX, Timer, DE, E, const N: usize> serial::Write<u8> for Rs485<TX, RX, Timer, DE, N>
+where
+    TX: OutputPin<Error = E>,
+    RX: InputPin<Error = E>,
+    Timer: CountDown + Periodic,
+    DE: OutputPin<Error = E>,
+{
+    type Error = crate::serial::Error<E>;
+
+    fn write(&mut self, byte: u8) -> nb::Result<(), Self::Error> {
+        self.de.set_high().map_err(Error::Bus)?;
+        self.serial.write(byte)?;
+        self.serial.flush()?;
+        self.serial.wait_half_bits(2 * self.turnaround_bits);
+        self.de.set_low().map_err(Error::Bus)?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> nb::Result<(), Self::Error> {
+        self.serial.flush()
+    }
+}
+
+impl<TX, RX, Timer, DE, E, const N: usize> serial::Read<u8> for Rs485<TX, RX, Timer, DE, N>
+where
+    TX: OutputPin<Error = E>,
+    RX: InputPin<Error = E>,
+    Timer: CountDown + Periodic,
+    DE: OutputPin<Error = E>,
+{
+    type Error = crate::serial::Error<E>;
+
+    fn read(&mut self) -> nb::Result<u8, Self::Error> {
+        self.de.set_low().map_err(Error::Bus)?;
+        self.serial.read()
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::mock::{FlakyPin, Line, MockPin, MockTimer, Recorder, WavePin};
+    use core::cell::{Cell, RefCell};
+    use core::convert::Infallible;
+
+    type Rec = RefCell<Recorder<256>>;
+
+    /// RX level at `tick` for 8N1 frames starting at the given ticks, with
+    /// `ticks_per_bit` timer ticks per bit
+    fn frames(frames: &[(u32, u8)], ticks_per_bit: u32, tick: u32) -> bool {
+        for &(start, byte) in frames {
+            if tick < start {
+                continue;
+            }
+            match (tick - start) / ticks_per_bit {
+                0 => return false,
+                bit @ 1..=8 => return byte & (1 << (bit - 1)) != 0,
+                _ => {}
+            }
+        }
+        true
+    }
+
+    fn serial<'a>(
+        rec: &'a Rec,
+        wave: &'a dyn Fn(u32) -> bool,
+        rate: TimerRate,
+    ) -> Serial<MockPin<'a, 256>, WavePin<'a, 256>, MockTimer<'a, 256>, 4> {
+        Serial::new_with_timer_rate(
+            MockPin::new(rec, Line::Other(0)),
+            WavePin::new(rec, Line::Other(1), wave),
+            MockTimer::new(rec),
+            rate,
+        )
+        .with_rx_buffer()
+    }
+
+    #[test]
+    fn captures_frame_within_one_bit() {
+        for gap in 0..=2 {
+            let rec = RefCell::new(Recorder::new());
+            // the first frame is read up to the end of its stop bit at 20
+            let wave = |tick| frames(&[(0, 0x55), (20 + gap, 0xa3)], 2, tick);
+            let mut serial = serial(&rec, &wave, TimerRate::TwoX);
+            assert_eq!(serial.read_word().ok(), Some(0x55));
+            assert_eq!(serial.rx_buffer.pop(), Some(0xa3), "gap {}", gap);
+        }
+    }
+
+    #[test]
+    fn ignores_frame_after_window() {
+        let rec = RefCell::new(Recorder::new());
+        let wave = |tick| frames(&[(0, 0x55), (23, 0xa3)], 2, tick);
+        let mut serial = serial(&rec, &wave, TimerRate::TwoX);
+        assert_eq!(serial.read_word().ok(), Some(0x55));
+        assert!(serial.rx_buffer.is_empty());
+        assert_eq!(rec.borrow().time(), 22);
+    }
+
+    /// Transmitter whose TX waveform is recorded in `rec`, for replaying it
+    /// on RX with [replayed]
+    fn transmitter(rec: &Rec) -> Serial<MockPin<'_, 256>, MockPin<'_, 256>, MockTimer<'_, 256>> {
+        Serial::new_with_timer_rate(
+            MockPin::new(rec, Line::Other(0)),
+            MockPin::new(rec, Line::Other(1)),
+            MockTimer::new(rec),
+            TimerRate::TwoX,
+        )
+    }
+
+    /// TX level recorded by a [transmitter] at `tick`
+    fn replayed(tx_rec: &Rec, tick: u32) -> bool {
+        let tx_rec = tx_rec.borrow();
+        let mut events = tx_rec.events().iter().rev();
+        events.find(|e| e.time <= tick).is_none_or(|e| e.level)
+    }
+
+    /// Send `words` with the given frame format and receive them back by
+    /// replaying the recorded TX waveform on RX
+    fn loopback(data_bits: DataBits, stop_bits: StopBits, words: &[u16]) {
+        let tx_rec = RefCell::new(Recorder::new());
+        let mut tx = transmitter(&tx_rec);
+        tx.set_data_bits(data_bits);
+        tx.set_stop_bits(stop_bits);
+        for word in words {
+            tx.write_word(*word).unwrap();
+        }
+
+        let rec = RefCell::new(Recorder::new());
+        let wave = |tick| replayed(&tx_rec, tick);
+        let mut rx = serial(&rec, &wave, TimerRate::TwoX);
+        rx.set_data_bits(data_bits);
+        rx.set_stop_bits(stop_bits);
+        for word in words {
+            assert_eq!(rx.read_word().ok(), Some(*word), "{:?}", data_bits);
+        }
+    }
+
+    #[test]
+    fn loopback_seven_data_bits() {
+        loopback(DataBits::Seven, StopBits::One, &[0x43, 0x0e, 0x7e, 0x15]);
+    }
+
+    #[test]
+    fn loopback_nine_data_bits() {
+        loopback(DataBits::Nine, StopBits::One, &[0x1a5, 0x0ff, 0x100, 0x001]);
+    }
+
+    #[test]
+    fn loopback_two_stop_bits() {
+        loopback(DataBits::Eight, StopBits::Two, &[0x80, 0x01, 0xc4]);
+    }
+
+    #[test]
+    fn frame_round_trip() {
+        let tx_rec = RefCell::new(Recorder::new());
+        let mut tx = transmitter(&tx_rec);
+        // noise before the frame is skipped
+        serial::Write::write(&mut tx, 0x00).unwrap();
+        tx.send_frame(&[0x01, 0x7e, 0xff]).unwrap();
+        tx.send_frame(&[]).unwrap();
+
+        let rec = RefCell::new(Recorder::new());
+        let wave = |tick| replayed(&tx_rec, tick);
+        let mut rx = serial(&rec, &wave, TimerRate::TwoX);
+        let mut buf = [0; 4];
+        assert_eq!(rx.recv_frame(&mut buf).ok(), Some(3));
+        assert_eq!(buf[..3], [0x01, 0x7e, 0xff]);
+        assert_eq!(rx.recv_frame(&mut buf).ok(), Some(0));
+    }
+
+    #[test]
+    fn frame_checksum_mismatch() {
+        let tx_rec = RefCell::new(Recorder::new());
+        let mut tx = transmitter(&tx_rec);
+        for byte in [FRAME_START, 2, 0x12, 0x34, 2 ^ 0x12 ^ 0x34 ^ 0x01] {
+            serial::Write::write(&mut tx, byte).unwrap();
+        }
+
+        let rec = RefCell::new(Recorder::new());
+        let wave = |tick| replayed(&tx_rec, tick);
+        let mut rx = serial(&rec, &wave, TimerRate::TwoX);
+        assert!(matches!(rx.recv_frame(&mut [0; 2]), Err(Error::Checksum)));
+    }
+
+    #[test]
+    fn frame_overflow() {
+        let tx_rec = RefCell::new(Recorder::new());
+        let mut tx = transmitter(&tx_rec);
+        assert!(matches!(tx.send_frame(&[0; 256]), Err(Error::Overflow)));
+        assert!(tx_rec.borrow().events().is_empty());
+        tx.send_frame(&[1, 2, 3]).unwrap();
+
+        let rec = RefCell::new(Recorder::new());
+        let wave = |tick| replayed(&tx_rec, tick);
+        let mut rx = serial(&rec, &wave, TimerRate::TwoX);
+        assert!(matches!(rx.recv_frame(&mut [0; 2]), Err(Error::Overflow)));
+    }
+
+    /// Read from RX held low for `low_bits` bit periods at twice the baud
+    /// rate, with the given break threshold
+    ///
+    /// The driver waits for the release of a break without the timer, so the
+    /// levels are queued per read rather than derived from the time: two for
+    /// the start bit, one per data bit, two for the stop bit and one per
+    /// further bit period.
+    fn read_low(low_bits: u32, threshold: u32) -> nb::Result<u16, Error<Infallible>> {
+        let rec: Rec = RefCell::new(Recorder::new());
+        let mut serial = Serial::new_with_timer_rate(
+            MockPin::new(&rec, Line::Other(0)),
+            MockPin::new(&rec, Line::Other(1)),
+            MockTimer::new(&rec),
+            TimerRate::TwoX,
+        );
+        serial.set_break_threshold_bits(threshold);
+        for _ in 0..low_bits + 2 {
+            rec.borrow_mut().queue_reads(&[false]);
+        }
+        serial.read_word()
+    }
+
+    #[test]
+    fn break_at_threshold() {
+        assert!(matches!(
+            read_low(11, 11),
+            Err(nb::Error::Other(Error::Break))
+        ));
+        assert!(matches!(read_low(10, 11), Ok(0)));
+        assert!(matches!(
+            read_low(13, 13),
+            Err(nb::Error::Other(Error::Break))
+        ));
+        assert!(matches!(read_low(12, 13), Ok(0)));
+    }
+
+    #[test]
+    fn deferred_flush_sends_back_to_back() {
+        let tx_rec = RefCell::new(Recorder::new());
+        let mut tx = transmitter(&tx_rec);
+        tx.set_deferred_flush(true);
+        for byte in 0..TX_BUFFER_LEN as u8 {
+            serial::Write::write(&mut tx, byte).unwrap();
+        }
+        assert!(tx_rec.borrow().events().is_empty());
+
+        // a write to the full buffer sends it first, without gaps
+        serial::Write::write(&mut tx, 0xa5).unwrap();
+        assert_eq!(tx_rec.borrow().time(), 20 * TX_BUFFER_LEN as u32);
+        serial::Write::flush(&mut tx).unwrap();
+        assert_eq!(tx_rec.borrow().time(), 20 * (TX_BUFFER_LEN as u32 + 1));
+
+        let rec = RefCell::new(Recorder::new());
+        let wave = |tick| replayed(&tx_rec, tick);
+        let mut rx = serial(&rec, &wave, TimerRate::TwoX);
+        for byte in (0..TX_BUFFER_LEN as u8).chain([0xa5]) {
+            assert_eq!(serial::Read::read(&mut rx).ok(), Some(byte));
+        }
+    }
+
+    #[test]
+    fn wake_sends_break_and_sync() {
+        let rec = RefCell::new(Recorder::new());
+        let mut tx = transmitter(&rec);
+        tx.wake().unwrap();
+        assert!(rec.borrow().events().is_empty());
+
+        tx.set_wake_pattern(13, &[0x55]);
+        tx.wake().unwrap();
+        let rec = rec.borrow();
+        let mut events = rec.events().iter().map(|e| (e.time, e.level));
+        // 13 bit break and one bit delimiter at two ticks per bit
+        assert_eq!(events.next(), Some((0, false)));
+        assert_eq!(events.next(), Some((26, true)));
+        // start bit, 0x55 LSB first, stop bit
+        let frame = [
+            false, true, false, true, false, true, false, true, false, true,
+        ];
+        assert!(events.eq(frame
+            .iter()
+            .enumerate()
+            .map(|(bit, level)| (28 + 2 * bit as u32, *level))));
+    }
+
+    #[test]
+    fn rs485_driver_enable_around_write() {
+        const DE: Line = Line::Other(2);
+        let rec = RefCell::new(Recorder::new());
+        let mut rs485 = Rs485::new(transmitter(&rec), MockPin::new(&rec, DE), 2);
+        rec.borrow_mut().clear();
+        serial::Write::write(&mut rs485, 0xa5).unwrap();
+
+        let rec = rec.borrow();
+        let events = rec.events();
+        let mut de = events.iter().filter(|e| e.line == DE);
+        // DE rises before the start bit and falls two bit periods after the
+        // end of the stop bit at tick 20
+        assert_eq!(de.next().map(|e| (e.time, e.level)), Some((0, true)));
+        assert_eq!(de.next().map(|e| (e.time, e.level)), Some((24, false)));
+        assert!(de.next().is_none());
+        let first_tx = events.iter().position(|e| e.line == Line::Other(0));
+        let de_high = events.iter().position(|e| e.line == DE);
+        assert!(de_high < first_tx);
+        assert_eq!(rec.time(), 24);
+    }
+
+    #[cfg(feature = "embedded-io")]
+    #[test]
+    fn embedded_io_round_trip() {
+        use embedded_io::{Error as _, ErrorKind, Read, Write};
+
+        let tx_rec = RefCell::new(Recorder::new());
+        let mut tx = transmitter(&tx_rec);
+        tx.set_deferred_flush(true);
+        assert_eq!(tx.write(b"hi!").ok(), Some(3));
+        assert!(tx_rec.borrow().events().is_empty());
+        tx.flush().unwrap();
+
+        let rec = RefCell::new(Recorder::new());
+        let wave = |tick| replayed(&tx_rec, tick);
+        let mut rx = serial(&rec, &wave, TimerRate::TwoX);
+        assert_eq!(rx.read(&mut []).ok(), Some(0));
+        // the bytes following back-to-back come from the receive buffer
+        let mut buf = [0; 8];
+        assert_eq!(rx.read(&mut buf).ok(), Some(3));
+        assert_eq!(&buf[..3], b"hi!");
+
+        assert_eq!(Error::<Infallible>::Checksum.kind(), ErrorKind::InvalidData);
+        assert_eq!(Error::<Infallible>::Break.kind(), ErrorKind::Other);
+    }
+
+    #[test]
+    fn usable_after_tx_error() {
+        let rec = RefCell::new(Recorder::<256>::new());
+        let (tx_fault, rx_fault) = (Cell::new(None), Cell::new(None));
+        let mut serial = Serial::new(
+            FlakyPin::new(&rec, Line::Other(0), &tx_fault),
+            FlakyPin::new(&rec, Line::Other(1), &rx_fault),
+            MockTimer::new(&rec),
+        );
+
+        // fail on the third data bit
+        tx_fault.set(Some(3));
+        assert!(matches!(
+            serial::Write::write(&mut serial, 0x00),
+            Err(nb::Error::Other(Error::Bus(())))
+        ));
+        assert_eq!(rec.borrow().events().last().map(|e| e.level), Some(true));
+
+        rec.borrow_mut().clear();
+        serial::Write::write(&mut serial, 0xa5).unwrap();
+        let rec = rec.borrow();
+        let levels = rec.events().iter().map(|event| event.level);
+        // start bit, 0xa5 LSB first, stop bit
+        let frame = [
+            false, true, false, true, false, false, true, false, true, true,
+        ];
+        assert!(levels.eq(frame));
+    }
+
+    #[test]
+    fn new_checked_derives_timer_rate() {
+        let rec = RefCell::new(Recorder::<256>::new());
+        let serial = |timer_hz| {
+            Serial::new_checked(
+                MockPin::new(&rec, Line::Other(0)),
+                MockPin::new(&rec, Line::Other(1)),
+                MockTimer::new(&rec),
+                9600,
+                timer_hz,
+            )
+            .timer_rate
+        };
+        assert_eq!(serial(9600), TimerRate::OneX);
+        assert_eq!(serial(19_000), TimerRate::TwoX);
+    }
+
+    #[test]
+    #[should_panic(expected = "timer must run at once or twice the baud rate")]
+    fn new_checked_rejects_timer_rate_in_debug_builds() {
+        let rec = RefCell::new(Recorder::<256>::new());
+        Serial::new_checked(
+            MockPin::new(&rec, Line::Other(0)),
+            MockPin::new(&rec, Line::Other(1)),
+            MockTimer::new(&rec),
+            9600,
+            14_400,
+        );
+    }
+
+    /// RX level at `tick` for a frame of `byte` from tick 0, with every data
+    /// bit corrupted outside the ticks `window` of the bit and at tick
+    /// `glitch` inside it
+    fn noisy_frame(
+        byte: u8,
+        ticks_per_bit: u32,
+        window: (u32, u32),
+        glitch: u32,
+        tick: u32,
+    ) -> bool {
+        let level = frames(&[(0, byte)], ticks_per_bit, tick);
+        let bit = tick / ticks_per_bit;
+        let offset = tick % ticks_per_bit;
+        let corrupt = offset < window.0 || offset > window.1 || offset == window.0 + glitch;
+        if (1..=8).contains(&bit) && corrupt {
+            !level
+        } else {
+            level
+        }
+    }
+
+    fn vote(rate: TimerRate, samples: SamplesPerBit, window: (u32, u32)) {
+        let count = samples.count();
+        let ticks_per_bit = match rate {
+            TimerRate::OneX => count,
+            TimerRate::TwoX => 2 * count,
+        };
+        for glitch in 0..count {
+            let rec = RefCell::new(Recorder::new());
+            let wave = |tick| noisy_frame(0xa5, ticks_per_bit, window, glitch, tick);
+            let mut serial = serial(&rec, &wave, rate);
+            serial.set_samples_per_bit(samples);
+            assert_eq!(serial.read_word().ok(), Some(0xa5), "glitch {}", glitch);
+        }
+    }
+
+    #[test]
+    fn vote_three_samples() {
+        // the samples fill the bit at the baud rate, and are centred in it
+        // at twice the baud rate
+        vote(TimerRate::OneX, SamplesPerBit::Three, (0, 2));
+        vote(TimerRate::TwoX, SamplesPerBit::Three, (2, 4));
+    }
+
+    #[test]
+    fn vote_five_samples() {
+        vote(TimerRate::OneX, SamplesPerBit::Five, (0, 4));
+        vote(TimerRate::TwoX, SamplesPerBit::Five, (3, 7));
     }
 }
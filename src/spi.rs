@@ -8,29 +8,93 @@
 //!
 //! The timer must be configured to twice the desired communication frequency.
 //!
-//! SS/CS (slave select) must be handled independently.
+//! SS/CS (slave select) must be handled independently, or by wrapping the
+//! device in [SpiWithCs].
+//!
+//! Every call clocks whole bits and returns with SCK at its idle level, so a
+//! transfer to a selected slave can be split across any number of calls with
+//! other work in between. [SPI::begin_transfer] and [SPI::end_transfer]
+//! additionally put the lines into a known state around such a transfer,
+//! e.g. after the mode was changed.
+//!
+//! A call that fails part-way leaves the driver ready for the next one: SCK
+//! is driven to its idle level and MOSI to its idle level, if one is set,
+//! ignoring any further pin error, and the received bits are discarded, so
+//! [read](FullDuplex::read) returns `NoData` until the next byte is sent.
+//! The slave may still hold a partial byte; deselecting it resynchronises
+//! most devices.
 //!
 //! MSB-first and LSB-first bit orders are supported.
 //!
+//! [SpiSlave] acts as a slave clocked by an external master instead.
+//!
 
 pub use embedded_hal::spi::{MODE_0, MODE_1, MODE_2, MODE_3};
 
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use embedded_hal::blocking::delay::DelayUs;
 use embedded_hal::digital::v2::{InputPin, OutputPin};
-use embedded_hal::spi::{FullDuplex, Mode, Polarity};
+use embedded_hal::spi::{FullDuplex, Mode, Phase, Polarity};
 use embedded_hal::timer::{CountDown, Periodic};
 use nb::block;
 
+use crate::timing::{close_to, half_period_wait, DelayTimer};
+
 /// Error type
 #[derive(Debug)]
 pub enum Error<E> {
-    /// Communication error
-    Bus(E),
+    /// Communication error on the given line
+    Bus(Line, E),
     /// Attempted read without input data
     NoData,
+    /// Transfer cancelled through the abort flag
+    Aborted,
+}
+
+/// Line on which a [Error::Bus] occurred
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Line {
+    /// Clock
+    Sck,
+    /// Master output, slave input
+    Mosi,
+    /// Master input, slave output
+    Miso,
+    /// Chip select
+    Cs,
+    /// Clock enable
+    Enable,
+}
+
+impl<E> core::fmt::Display for Error<E>
+where
+    E: core::fmt::Debug,
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Error::Bus(line, e) => crate::error::fmt_bus_error(f, Some(line), e),
+            Error::NoData => f.write_str("no data"),
+            Error::Aborted => f.write_str("aborted"),
+        }
+    }
+}
+
+#[cfg(feature = "core-error")]
+impl<E> core::error::Error for Error<E>
+where
+    E: core::error::Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            Error::Bus(_, e) => Some(e),
+            _ => None,
+        }
+    }
 }
 
 /// Transmission bit order
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum BitOrder {
     /// Most significant bit first
     MSBFirst,
@@ -45,22 +109,69 @@ impl Default for BitOrder {
     }
 }
 
+/// SCK edge within a clock cycle, see [SPI::new_with_edges]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ClockEdge {
+    /// First edge, leaving the idle level
+    Leading,
+    /// Second edge, returning to the idle level
+    Trailing,
+}
+
+/// Hooks observing the SPI lines, for tracing without a logic analyzer
+///
+/// All methods default to doing nothing. The unit type `()` is the default
+/// tracer of [SPI] and compiles to no code; install another one with
+/// [SPI::with_tracer].
+pub trait SpiTrace {
+    /// Called after SCK has been driven to `level`
+    fn on_clock(&mut self, _level: bool) {}
+
+    /// Called after MOSI has been driven to `bit`
+    fn on_mosi(&mut self, _bit: bool) {}
+
+    /// Called after MISO has been sampled as `bit`
+    fn on_miso(&mut self, _bit: bool) {}
+}
+
+impl SpiTrace for () {}
+
 /// A Full-Duplex SPI implementation, takes 3 pins, and a timer running at 2x
 /// the desired SPI frequency.
-pub struct SPI<Miso, Mosi, Sck, Timer>
+///
+/// `Tr` is the [SpiTrace] tracer, `()` unless set with
+/// [with_tracer](SPI::with_tracer).
+pub struct SPI<Miso, Mosi, Sck, Timer, Tr = ()>
 where
     Miso: InputPin,
     Mosi: OutputPin,
     Sck: OutputPin,
     Timer: CountDown + Periodic,
+    Tr: SpiTrace,
 {
     mode: Mode,
     miso: Miso,
     mosi: Mosi,
     sck: Sck,
-    timer: Timer,
+    timer: Option<Timer>,
     read_val: Option<u8>,
+    read_bits: u8,
     bit_order: BitOrder,
+    mosi_idle: Option<bool>,
+    clk_high_ticks: u32,
+    clk_low_ticks: u32,
+    sample_edge: ClockEdge,
+    shift_edge: ClockEdge,
+    tracer: Tr,
+    abort: Option<&'static AtomicBool>,
+    sample_count: u8,
+    fill_byte: u8,
+    miso_settle_ticks: u32,
+    late_sample_ticks: u32,
+    configured_hz: u32,
+    tick_count: u32,
+    cs_setup_ticks: u32,
+    cs_hold_ticks: u32,
 }
 
 impl<Miso, Mosi, Sck, Timer, E> SPI<Miso, Mosi, Sck, Timer>
@@ -72,14 +183,99 @@ where
 {
     /// Create instance
     pub fn new(mode: Mode, miso: Miso, mosi: Mosi, sck: Sck, timer: Timer) -> Self {
+        Self::new_with_clock_ticks(mode, miso, mosi, sck, timer, 1, 1)
+    }
+
+    /// Create instance, checking the timer frequency
+    ///
+    /// `bus_hz` is the intended SCK frequency and `timer_hz` the frequency
+    /// the timer was started with.
+    ///
+    /// # Panics
+    ///
+    /// In debug builds, if `timer_hz` is not within 10 % of twice `bus_hz`,
+    /// e.g. when the timer was started at the SCK frequency. Release builds
+    /// skip the check.
+    pub fn new_checked(
+        mode: Mode,
+        miso: Miso,
+        mosi: Mosi,
+        sck: Sck,
+        timer: Timer,
+        bus_hz: u32,
+        timer_hz: u32,
+    ) -> Self {
+        debug_assert!(
+            close_to(timer_hz, bus_hz.saturating_mul(2)),
+            "timer must run at twice the SPI clock"
+        );
+        let mut spi = Self::new(mode, miso, mosi, sck, timer);
+        spi.configured_hz = timer_hz;
+        spi
+    }
+
+    /// Create instance with the given bit order
+    ///
+    /// Equivalent to [new](Self::new) followed by
+    /// [set_bit_order](Self::set_bit_order), for LSB-first devices set up in
+    /// a single expression. The order applies to received bytes as well as
+    /// sent ones.
+    pub fn new_with_order(
+        mode: Mode,
+        miso: Miso,
+        mosi: Mosi,
+        sck: Sck,
+        timer: Timer,
+        order: BitOrder,
+    ) -> Self {
+        let mut spi = Self::new(mode, miso, mosi, sck, timer);
+        spi.bit_order = order;
+        spi
+    }
+
+    /// Create instance with asymmetric clock phases
+    ///
+    /// SCK is held high for `clk_high_ticks` and low for `clk_low_ticks` timer
+    /// ticks per bit, for slaves that need e.g. a longer clock-low period.
+    /// [new](Self::new) uses a single tick for both.
+    pub fn new_with_clock_ticks(
+        mode: Mode,
+        miso: Miso,
+        mosi: Mosi,
+        sck: Sck,
+        timer: Timer,
+        clk_high_ticks: u32,
+        clk_low_ticks: u32,
+    ) -> Self {
+        let (sample_edge, shift_edge) = match mode.phase {
+            Phase::CaptureOnFirstTransition => (ClockEdge::Leading, ClockEdge::Trailing),
+            Phase::CaptureOnSecondTransition => (ClockEdge::Trailing, ClockEdge::Leading),
+        };
+
         let mut spi = SPI {
             mode,
             miso,
             mosi,
             sck,
-            timer,
+            timer: Some(timer),
             read_val: None,
+            read_bits: 0,
             bit_order: BitOrder::default(),
+            mosi_idle: None,
+            clk_high_ticks,
+            clk_low_ticks,
+            sample_edge,
+            shift_edge,
+            tracer: (),
+            abort: None,
+            sample_count: 1,
+            fill_byte: 0x00,
+            miso_settle_ticks: 0,
+            late_sample_ticks: 0,
+            configured_hz: 0,
+            tick_count: 0,
+            cs_setup_ticks: 0,
+            cs_hold_ticks: 0,
         };
 
         match mode.polarity {
@@ -91,11 +287,111 @@ where
         spi
     }
 
+    /// Create instance with explicit clock edges
+    ///
+    /// For slaves that fit none of the four standard modes, e.g. ones that
+    /// expect MOSI to change on the same edge MISO is sampled on. SCK idles
+    /// at `clock_idle`, MISO is sampled on `sample_edge` and MOSI changes on
+    /// `shift_edge`. [mode](Self::mode) reports the polarity and the phase
+    /// implied by `sample_edge`.
+    pub fn new_with_edges(
+        miso: Miso,
+        mosi: Mosi,
+        sck: Sck,
+        timer: Timer,
+        clock_idle: Polarity,
+        sample_edge: ClockEdge,
+        shift_edge: ClockEdge,
+    ) -> Self {
+        let phase = match sample_edge {
+            ClockEdge::Leading => Phase::CaptureOnFirstTransition,
+            ClockEdge::Trailing => Phase::CaptureOnSecondTransition,
+        };
+        let mode = Mode {
+            polarity: clock_idle,
+            phase,
+        };
+
+        let mut spi = Self::new(mode, miso, mosi, sck, timer);
+        spi.shift_edge = shift_edge;
+        spi
+    }
+
+    /// Install a tracer that observes every SCK edge, MOSI change and MISO
+    /// sample
+    pub fn with_tracer<Tr>(self, tracer: Tr) -> SPI<Miso, Mosi, Sck, Timer, Tr>
+    where
+        Tr: SpiTrace,
+    {
+        SPI {
+            mode: self.mode,
+            miso: self.miso,
+            mosi: self.mosi,
+            sck: self.sck,
+            timer: self.timer,
+            read_val: self.read_val,
+            read_bits: self.read_bits,
+            bit_order: self.bit_order,
+            mosi_idle: self.mosi_idle,
+            clk_high_ticks: self.clk_high_ticks,
+            clk_low_ticks: self.clk_low_ticks,
+            sample_edge: self.sample_edge,
+            shift_edge: self.shift_edge,
+            tracer,
+            abort: self.abort,
+            sample_count: self.sample_count,
+            fill_byte: self.fill_byte,
+            miso_settle_ticks: self.miso_settle_ticks,
+            late_sample_ticks: self.late_sample_ticks,
+            configured_hz: self.configured_hz,
+            tick_count: self.tick_count,
+            cs_setup_ticks: self.cs_setup_ticks,
+            cs_hold_ticks: self.cs_hold_ticks,
+        }
+    }
+}
+
+impl<Miso, Mosi, Sck, Timer, Tr, E> SPI<Miso, Mosi, Sck, Timer, Tr>
+where
+    Miso: InputPin<Error = E>,
+    Mosi: OutputPin<Error = E>,
+    Sck: OutputPin<Error = E>,
+    Timer: CountDown + Periodic,
+    Tr: SpiTrace,
+{
+    /// Get the tracer
+    pub fn tracer(&self) -> &Tr {
+        &self.tracer
+    }
+
+    /// Get the tracer mutably, e.g. to drain recorded events
+    pub fn tracer_mut(&mut self) -> &mut Tr {
+        &mut self.tracer
+    }
+
     /// Set transmission bit order
     pub fn set_bit_order(&mut self, order: BitOrder) {
         self.bit_order = order;
     }
 
+    /// Get transmission bit order
+    pub fn bit_order(&self) -> BitOrder {
+        self.bit_order
+    }
+
+    /// Get SPI mode
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// Set the level MOSI is driven to after each transmitted byte
+    ///
+    /// `Some(true)` idles MOSI high, `Some(false)` idles it low. With `None`
+    /// (the default) MOSI is left at the level of the last transmitted bit.
+    pub fn set_mosi_idle(&mut self, level: Option<bool>) {
+        self.mosi_idle = level;
+    }
+
     /// Allows for an access to the timer type.
     /// This can be used to change the speed.
     ///
@@ -115,124 +411,1868 @@ where
     where
         F: FnOnce(Timer) -> Timer,
     {
-        // Create a zeroed timer.
-        // This is unsafe, but its safety is guaranteed, though, because the zeroed timer is never used.
-        let timer = unsafe { core::mem::zeroed() };
-        // Get the timer in the struct.
-        let timer = core::mem::replace(&mut self.timer, timer);
-        // Give the timer to the closure and put the result back into the struct.
-        self.timer = f(timer);
+        if let Some(timer) = self.timer.take() {
+            self.timer = Some(f(timer));
+        }
     }
 
-    fn read_bit(&mut self) -> nb::Result<(), crate::spi::Error<E>> {
-        let is_miso_high = self.miso.is_high().map_err(Error::Bus)?;
-        let shifted_value = self.read_val.unwrap_or(0) << 1;
-        if is_miso_high {
-            self.read_val = Some(shifted_value | 1);
-        } else {
-            self.read_val = Some(shifted_value);
+    /// Replace the timer, dropping the current one.
+    ///
+    /// Use [access_timer](Self::access_timer) to reconfigure the existing
+    /// timer instead.
+    pub fn set_timer(&mut self, timer: Timer) {
+        self.timer = Some(timer);
+    }
+
+    /// Record the timer frequency in Hz for [protocol_hz](Self::protocol_hz)
+    pub fn set_configured_hz(&mut self, hz: u32) {
+        self.configured_hz = hz;
+    }
+
+    /// Approximate SCK frequency in Hz, half the configured timer frequency
+    ///
+    /// Extra clock high or low ticks make the actual rate lower. Returns 0 if
+    /// no timer frequency was recorded.
+    pub fn protocol_hz(&self) -> u32 {
+        self.configured_hz / 2
+    }
+
+    /// Return the number of timer ticks waited since the last call and reset
+    /// the counter.
+    ///
+    /// With the default clock phases a byte takes 16 ticks in every mode,
+    /// two per bit, so SCK runs at half the timer frequency; longer clock
+    /// phases and settle or CS delays add to that. Timing one transfer this
+    /// way shows the effective SPI clock for a given timer rate.
+    pub fn take_tick_count(&mut self) -> u32 {
+        core::mem::replace(&mut self.tick_count, 0)
+    }
+
+    /// Run a loopback self-test.
+    ///
+    /// MOSI must be jumpered to MISO externally. A known pattern is sent and
+    /// the readback compared against it, which confirms the wiring and the
+    /// timer configuration during board bring-up.
+    ///
+    /// Returns `Ok(false)` if any byte read back differs from the one sent.
+    pub fn self_test(&mut self) -> Result<bool, crate::spi::Error<E>> {
+        const PATTERN: [u8; 2] = [0xA5, 0x5A];
+
+        for byte in PATTERN {
+            block!(self.send(byte))?;
+            if block!(self.read())? != byte {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Probe slaves on separate CS pins, for finding populated slots
+    ///
+    /// For each pin in `cs_pins`, CS is asserted (low), the lines are
+    /// prepared as in [begin_transfer](Self::begin_transfer), `probe` is
+    /// transferred and CS is deasserted again, with the same setup and hold
+    /// times as [SpiWithCs]; the byte read back is stored at the same index
+    /// of `out`. An empty slot typically reads back 0x00 or
+    /// 0xFF, depending on the MISO pull. Only as many slots as both slices
+    /// hold are probed. CS is deasserted even if the transfer fails, and the
+    /// scan stops at the first error.
+    pub fn probe_slaves(
+        &mut self,
+        cs_pins: &mut [&mut dyn OutputPin<Error = E>],
+        probe: u8,
+        out: &mut [u8],
+    ) -> Result<(), crate::spi::Error<E>> {
+        for (cs, slot) in cs_pins.iter_mut().zip(out.iter_mut()) {
+            *slot = self.with_cs_pin(*cs, |spi| {
+                spi.begin_transfer()?;
+                block!(spi.send(probe))?;
+                let byte = block!(spi.read())?;
+                spi.end_transfer()?;
+                Ok(byte)
+            })?;
         }
         Ok(())
     }
 
-    #[inline]
-    fn set_clk_high(&mut self) -> Result<(), crate::spi::Error<E>> {
-        self.sck.set_high().map_err(Error::Bus)
+    /// Set the number of timer ticks between asserting CS and the first clock
+    /// edge, for [SpiWithCs] and [probe_slaves](Self::probe_slaves).
+    /// Defaults to zero.
+    pub fn set_cs_setup_ticks(&mut self, ticks: u32) {
+        self.cs_setup_ticks = ticks;
     }
 
-    #[inline]
-    fn set_clk_low(&mut self) -> Result<(), crate::spi::Error<E>> {
-        self.sck.set_low().map_err(Error::Bus)
+    /// Set the number of timer ticks between the last clock edge and
+    /// deasserting CS, for [SpiWithCs] and
+    /// [probe_slaves](Self::probe_slaves). Defaults to zero.
+    pub fn set_cs_hold_ticks(&mut self, ticks: u32) {
+        self.cs_hold_ticks = ticks;
     }
 
-    #[inline]
-    fn wait_for_timer(&mut self) {
-        block!(self.timer.wait()).ok();
+    /// Run `f` with `cs` asserted, deasserting it afterwards even if `f` fails
+    fn with_cs_pin<CS, T, F>(&mut self, cs: &mut CS, f: F) -> Result<T, crate::spi::Error<E>>
+    where
+        CS: OutputPin<Error = E> + ?Sized,
+        F: FnOnce(&mut Self) -> Result<T, crate::spi::Error<E>>,
+    {
+        cs.set_low().map_err(|e| Error::Bus(Line::Cs, e))?;
+        self.wait_for_timer_ticks(self.cs_setup_ticks);
+
+        let result = f(self);
+
+        self.wait_for_timer_ticks(self.cs_hold_ticks);
+        cs.set_high().map_err(|e| Error::Bus(Line::Cs, e))?;
+
+        result
     }
-}
 
-impl<Miso, Mosi, Sck, Timer, E> FullDuplex<u8> for SPI<Miso, Mosi, Sck, Timer>
-where
-    Miso: InputPin<Error = E>,
-    Mosi: OutputPin<Error = E>,
-    Sck: OutputPin<Error = E>,
-    Timer: CountDown + Periodic,
-{
-    type Error = crate::spi::Error<E>;
+    /// Emit `count` dummy clock cycles.
+    ///
+    /// SCK is toggled with the polarity and phase of the configured mode while
+    /// MOSI is held at `mosi_level` and MISO is ignored, as required between
+    /// the command and data phases of many flash read commands.
+    pub fn dummy_clocks(
+        &mut self,
+        count: usize,
+        mosi_level: bool,
+    ) -> Result<(), crate::spi::Error<E>> {
+        self.reset_on_error(|spi| {
+            spi.set_mosi(mosi_level)?;
+            for _ in 0..count {
+                spi.clock_bit(None, false)?;
+            }
+            spi.idle_mosi()
+        })
+    }
 
-    #[inline]
-    fn read(&mut self) -> nb::Result<u8, Self::Error> {
-        match self.read_val {
-            Some(val) => Ok(val),
-            None => Err(nb::Error::Other(crate::spi::Error::NoData)),
+    /// Read MISO `count` times per bit and take the majority
+    ///
+    /// Improves noise immunity on noisy boards. The reads follow each other
+    /// directly at the sampling edge, slightly lengthening that clock phase.
+    /// A tie with an even count reads as low. The default of 1 samples once;
+    /// 0 is treated as 1.
+    pub fn set_sample_count(&mut self, count: u8) {
+        self.sample_count = count.max(1);
+    }
+
+    /// Prepare for a transfer split across several calls
+    ///
+    /// Drives SCK to the idle level of the configured mode and waits one idle
+    /// clock phase, so the first edge of the following call is a clean
+    /// leading edge. Call it after selecting the slave.
+    pub fn begin_transfer(&mut self) -> Result<(), crate::spi::Error<E>> {
+        self.set_clk_idle()?;
+        let idle_ticks = match self.mode.polarity {
+            Polarity::IdleLow => self.clk_low_ticks,
+            Polarity::IdleHigh => self.clk_high_ticks,
+        };
+        self.wait_for_timer_ticks(idle_ticks);
+        Ok(())
+    }
+
+    /// Finish a transfer started with [begin_transfer](Self::begin_transfer)
+    ///
+    /// Drives SCK to its idle level and MOSI to the idle level set with
+    /// [set_mosi_idle](Self::set_mosi_idle), if any. Call it before
+    /// deselecting the slave.
+    pub fn end_transfer(&mut self) -> Result<(), crate::spi::Error<E>> {
+        self.set_clk_idle()?;
+        self.idle_mosi()
+    }
+
+    /// Set the number of extra ticks to wait before sampling MISO
+    ///
+    /// For buses where MISO is open-drain with a shared pull-up, e.g.
+    /// multi-drop SPI-like buses, a released line rises slowly through the
+    /// pull-up and a push-pull sampling point can still read it low. The
+    /// wait is inserted right before MISO is read, lengthening the clock
+    /// phase it is sampled in. Configure the MISO pin as an input, with
+    /// its internal pull-up enabled if there is no external one. Defaults to
+    /// zero.
+    pub fn set_miso_settle_ticks(&mut self, ticks: u32) {
+        self.miso_settle_ticks = ticks;
+    }
+
+    /// Set the number of ticks MISO is sampled after the sampling edge
+    ///
+    /// On long cables the slave's answer arrives late and sampling at the
+    /// nominal edge can catch the previous bit. MISO is instead read `ticks`
+    /// into the clock phase following that edge, and the rest of the phase
+    /// is waited afterwards, so unlike
+    /// [set_miso_settle_ticks](Self::set_miso_settle_ticks) the bit rate is
+    /// unchanged. The delay is limited to the length of that phase; create
+    /// the instance with [new_with_clock_ticks](Self::new_with_clock_ticks)
+    /// for longer phases and a finer choice of sample point. Defaults to
+    /// zero.
+    pub fn set_late_sample_ticks(&mut self, ticks: u32) {
+        self.late_sample_ticks = ticks;
+    }
+
+    /// Cancel transfers when `flag` is set, e.g. from an interrupt handler
+    ///
+    /// The flag is checked before every byte, so a transfer in progress ends
+    /// with `Err(Aborted)` after the current byte, with SCK idle. It stays
+    /// set until the application clears it, and while set every transfer
+    /// fails. `None` (the default) disables the check.
+    pub fn set_abort_flag(&mut self, flag: Option<&'static AtomicBool>) {
+        self.abort = flag;
+    }
+
+    /// Clock a single bit, returning the MISO sample
+    ///
+    /// MOSI is driven to `out` and one clock cycle is generated according to
+    /// the configured mode, the same as for each bit of a byte. This allows
+    /// transfers of any bit length, e.g. a leading mode bit or a 9-bit
+    /// address. MOSI is left at `out` rather than the idle level, so bits can
+    /// be chained; the bit order setting does not apply.
+    pub fn transfer_bit(&mut self, out: bool) -> Result<bool, crate::spi::Error<E>> {
+        self.reset_on_error(|spi| {
+            spi.clock_bit(Some(out), true)?;
+            Ok(spi.read_val.unwrap_or(0) & 0b1 == 1)
+        })
+    }
+
+    /// Take the bits received so far, as `(value, count)`
+    ///
+    /// Received bits are shifted into the low end of `value`, so after
+    /// [transfer_bit](Self::transfer_bit) calls the most recent bit is bit 0.
+    /// `count` is the number of valid bits counted since the last call. The
+    /// value only holds eight bits, so after more than eight `transfer_bit`
+    /// calls it keeps the last eight and `count` stays at 8. Both are reset,
+    /// so the next bits start a new value.
+    pub fn take_partial(&mut self) -> (u8, u8) {
+        let value = self.read_val.take().unwrap_or(0);
+        let count = core::mem::replace(&mut self.read_bits, 0);
+        (value, count)
+    }
+
+    /// Clock in one byte while holding MOSI at `mosi_level`.
+    pub fn read_byte(&mut self, mosi_level: bool) -> Result<u8, crate::spi::Error<E>> {
+        self.reset_on_error(|spi| {
+            spi.check_abort()?;
+            spi.set_mosi(mosi_level)?;
+            for _ in 0..8 {
+                spi.clock_bit(None, true)?;
+            }
+            spi.finish_byte();
+            spi.idle_mosi()?;
+
+            Ok(spi.read_val.unwrap_or(0))
+        })
+    }
+
+    /// Clock in `buf.len()` bytes while holding MOSI at `mosi_level`.
+    ///
+    /// Named differently from [FullDuplex::read] so the trait method remains
+    /// callable with method syntax.
+    pub fn read_bytes(
+        &mut self,
+        buf: &mut [u8],
+        mosi_level: bool,
+    ) -> Result<(), crate::spi::Error<E>> {
+        for byte in buf.iter_mut() {
+            *byte = self.read_byte(mosi_level)?;
         }
+        Ok(())
     }
 
-    fn send(&mut self, byte: u8) -> nb::Result<(), Self::Error> {
-        for bit_offset in 0..8 {
-            let out_bit = match self.bit_order {
-                BitOrder::MSBFirst => (byte >> (7 - bit_offset)) & 0b1,
-                BitOrder::LSBFirst => (byte >> bit_offset) & 0b1,
-            };
+    /// Clock in `buf.len()` bytes while sending `fill` for each of them.
+    ///
+    /// Suits read phases where MOSI has to carry a specific byte, such as
+    /// 0xFF for NOR flash, without building a dummy write buffer. `fill` is
+    /// sent with the configured mode and bit order.
+    pub fn read_with_fill(&mut self, buf: &mut [u8], fill: u8) -> Result<(), crate::spi::Error<E>> {
+        for byte in buf.iter_mut() {
+            block!(self.send(fill))?;
+            *byte = block!(self.read())?;
+        }
+        Ok(())
+    }
+
+    /// Transfer a fixed number of bytes, returning the bytes read
+    ///
+    /// Equivalent to the blocking `transfer`, but takes and returns an array
+    /// instead of a mutable slice, which suits fixed-size register blocks.
+    pub fn transfer_array<const N: usize>(
+        &mut self,
+        mut buf: [u8; N],
+    ) -> Result<[u8; N], crate::spi::Error<E>> {
+        for byte in buf.iter_mut() {
+            block!(self.send(*byte))?;
+            *byte = block!(self.read())?;
+        }
+        Ok(buf)
+    }
+
+    /// Set the byte sent by [transfer_split](Self::transfer_split) once the
+    /// write buffer is exhausted, 0x00 by default
+    pub fn set_fill_byte(&mut self, byte: u8) {
+        self.fill_byte = byte;
+    }
+
+    /// Transfer with separate write and read buffers of any length
+    ///
+    /// Clocks the longer of the two lengths. Past the end of `write` the
+    /// fill byte is sent, see [set_fill_byte](Self::set_fill_byte), and
+    /// bytes received past the end of `read` are discarded. This matches
+    /// `SpiBus::transfer` of embedded-hal 1.0.
+    ///
+    /// Named differently from the blocking `Transfer::transfer` so the trait
+    /// method remains callable with method syntax.
+    pub fn transfer_split(
+        &mut self,
+        write: &[u8],
+        read: &mut [u8],
+    ) -> Result<(), crate::spi::Error<E>> {
+        for i in 0..write.len().max(read.len()) {
+            block!(self.send(write.get(i).copied().unwrap_or(self.fill_byte)))?;
+            let byte = block!(self.read())?;
+            if let Some(slot) = read.get_mut(i) {
+                *slot = byte;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write bytes produced by an iterator
+    ///
+    /// Bytes are clocked out as they are produced, with the configured mode
+    /// and bit order, so long computed streams need no buffer. The iterator
+    /// is advanced between bytes, which stretches the gap between them by the
+    /// time `next` takes; keep it cheap where the byte rate matters.
+    pub fn write_iter(
+        &mut self,
+        iter: impl IntoIterator<Item = u8>,
+    ) -> Result<(), crate::spi::Error<E>> {
+        for byte in iter {
+            block!(self.send(byte))?;
+        }
+        Ok(())
+    }
 
-            if out_bit == 1 {
-                self.mosi.set_high().map_err(Error::Bus)?;
-            } else {
-                self.mosi.set_low().map_err(Error::Bus)?;
+    /// Write a large buffer in chunks, calling `between` after each chunk
+    ///
+    /// Bit timing is preserved within a chunk of `chunk` bytes, while the
+    /// closure can service a watchdog or a cooperative scheduler between
+    /// chunks. A `chunk` of zero is treated as one.
+    pub fn write_chunked(
+        &mut self,
+        data: &[u8],
+        chunk: usize,
+        mut between: impl FnMut(),
+    ) -> Result<(), crate::spi::Error<E>> {
+        let mut chunks = data.chunks(chunk.max(1)).peekable();
+        while let Some(bytes) = chunks.next() {
+            for byte in bytes {
+                block!(self.send(*byte))?;
+            }
+            if chunks.peek().is_some() {
+                between();
             }
+        }
+        Ok(())
+    }
 
-            match self.mode {
-                MODE_0 => {
-                    self.wait_for_timer();
-                    self.set_clk_high()?;
-                    self.read_bit()?;
-                    self.wait_for_timer();
-                    self.set_clk_low()?;
+    /// Run `f`, returning the lines and receive state to idle if it fails.
+    ///
+    /// Pin errors while idling the lines are dropped in favour of the
+    /// original error.
+    fn reset_on_error<T, F>(&mut self, f: F) -> Result<T, crate::spi::Error<E>>
+    where
+        F: FnOnce(&mut Self) -> Result<T, crate::spi::Error<E>>,
+    {
+        let result = f(self);
+        if result.is_err() {
+            self.read_val = None;
+            self.read_bits = 0;
+            self.set_clk_idle().ok();
+            self.idle_mosi().ok();
+        }
+        result
+    }
+
+    /// Clock one bit according to the configured mode, driving MOSI to
+    /// `mosi` on the shift edge if given and sampling MISO if `sample` is set.
+    fn clock_bit(&mut self, mosi: Option<bool>, sample: bool) -> Result<(), crate::spi::Error<E>> {
+        let (idle_ticks, active_ticks) = match self.mode.polarity {
+            Polarity::IdleLow => (self.clk_low_ticks, self.clk_high_ticks),
+            Polarity::IdleHigh => (self.clk_high_ticks, self.clk_low_ticks),
+        };
+        let shift_leading = self.shift_edge == ClockEdge::Leading;
+
+        match self.sample_edge {
+            ClockEdge::Leading => {
+                if !shift_leading {
+                    self.shift_mosi(mosi)?;
                 }
-                MODE_1 => {
-                    self.set_clk_high()?;
-                    self.wait_for_timer();
+                self.wait_for_timer_ticks(idle_ticks);
+                if shift_leading {
+                    self.shift_mosi(mosi)?;
+                }
+                self.set_clk_active()?;
+                let late_ticks = self.late_sample_ticks.min(active_ticks);
+                self.wait_for_timer_ticks(late_ticks);
+                if sample {
                     self.read_bit()?;
-                    self.set_clk_low()?;
-                    self.wait_for_timer();
                 }
-                MODE_2 => {
-                    self.wait_for_timer();
-                    self.set_clk_low()?;
+                self.wait_for_timer_ticks(active_ticks - late_ticks);
+                self.set_clk_idle()?;
+            }
+            ClockEdge::Trailing => {
+                if shift_leading {
+                    self.shift_mosi(mosi)?;
+                }
+                self.set_clk_active()?;
+                self.wait_for_timer_ticks(active_ticks);
+                if !shift_leading {
+                    self.shift_mosi(mosi)?;
+                }
+                let late_ticks = self.late_sample_ticks.min(idle_ticks);
+                if sample && late_ticks == 0 {
                     self.read_bit()?;
-                    self.wait_for_timer();
-                    self.set_clk_high()?;
                 }
-                MODE_3 => {
-                    self.set_clk_low()?;
-                    self.wait_for_timer();
+                self.set_clk_idle()?;
+                self.wait_for_timer_ticks(late_ticks);
+                if sample && late_ticks > 0 {
                     self.read_bit()?;
-                    self.set_clk_high()?;
-                    self.wait_for_timer();
                 }
+                self.wait_for_timer_ticks(idle_ticks - late_ticks);
             }
         }
 
         Ok(())
     }
-}
 
-impl<Miso, Mosi, Sck, Timer, E> embedded_hal::blocking::spi::transfer::Default<u8>
-    for SPI<Miso, Mosi, Sck, Timer>
-where
-    Miso: InputPin<Error = E>,
-    Mosi: OutputPin<Error = E>,
-    Sck: OutputPin<Error = E>,
-    Timer: CountDown + Periodic,
-{
-}
+    #[inline]
+    fn check_abort(&self) -> Result<(), crate::spi::Error<E>> {
+        match self.abort {
+            Some(flag) if flag.load(Ordering::Relaxed) => Err(Error::Aborted),
+            _ => Ok(()),
+        }
+    }
 
-impl<Miso, Mosi, Sck, Timer, E> embedded_hal::blocking::spi::write::Default<u8>
-    for SPI<Miso, Mosi, Sck, Timer>
-where
-    Miso: InputPin<Error = E>,
-    Mosi: OutputPin<Error = E>,
-    Sck: OutputPin<Error = E>,
-    Timer: CountDown + Periodic,
-{
+    /// Read MISO by majority vote over `sample_count` reads
+    #[inline]
+    fn sample_miso(&self) -> Result<bool, crate::spi::Error<E>> {
+        let mut high = 0;
+        for _ in 0..self.sample_count {
+            if self.miso.is_high().map_err(|e| Error::Bus(Line::Miso, e))? {
+                high += 1;
+            }
+        }
+        Ok(high * 2 > self.sample_count)
+    }
+
+    /// Sample MISO and shift the bit into the low end of `read_val`
+    ///
+    /// Bits always arrive most recent first at bit 0, which is what
+    /// [transfer_bit](Self::transfer_bit) and
+    /// [take_partial](Self::take_partial) report. Whole bytes are put into
+    /// the configured bit order by [finish_byte](Self::finish_byte).
+    fn read_bit(&mut self) -> Result<(), crate::spi::Error<E>> {
+        self.wait_for_timer_ticks(self.miso_settle_ticks);
+        let is_miso_high = self.sample_miso()?;
+        self.tracer.on_miso(is_miso_high);
+        self.read_bits = (self.read_bits + 1).min(8);
+        let shifted_value = self.read_val.unwrap_or(0) << 1;
+        if is_miso_high {
+            self.read_val = Some(shifted_value | 1);
+        } else {
+            self.read_val = Some(shifted_value);
+        }
+        Ok(())
+    }
+
+    /// Reorder the eight bits just received according to the bit order
+    ///
+    /// With [BitOrder::LSBFirst] the first bit on the wire is bit 0 of the
+    /// byte, but [read_bit](Self::read_bit) has shifted it up to bit 7.
+    fn finish_byte(&mut self) {
+        if self.bit_order == BitOrder::LSBFirst {
+            self.read_val = self.read_val.map(u8::reverse_bits);
+        }
+    }
+
+    #[inline]
+    fn set_mosi(&mut self, high: bool) -> Result<(), crate::spi::Error<E>> {
+        if high {
+            self.mosi
+                .set_high()
+                .map_err(|e| Error::Bus(Line::Mosi, e))?;
+        } else {
+            self.mosi.set_low().map_err(|e| Error::Bus(Line::Mosi, e))?;
+        }
+        self.tracer.on_mosi(high);
+        Ok(())
+    }
+
+    #[inline]
+    fn shift_mosi(&mut self, mosi: Option<bool>) -> Result<(), crate::spi::Error<E>> {
+        match mosi {
+            Some(level) => self.set_mosi(level),
+            None => Ok(()),
+        }
+    }
+
+    #[inline]
+    fn idle_mosi(&mut self) -> Result<(), crate::spi::Error<E>> {
+        match self.mosi_idle {
+            Some(level) => self.set_mosi(level),
+            None => Ok(()),
+        }
+    }
+
+    #[inline]
+    fn set_clk_active(&mut self) -> Result<(), crate::spi::Error<E>> {
+        match self.mode.polarity {
+            Polarity::IdleLow => self.set_clk_high(),
+            Polarity::IdleHigh => self.set_clk_low(),
+        }
+    }
+
+    #[inline]
+    fn set_clk_idle(&mut self) -> Result<(), crate::spi::Error<E>> {
+        match self.mode.polarity {
+            Polarity::IdleLow => self.set_clk_low(),
+            Polarity::IdleHigh => self.set_clk_high(),
+        }
+    }
+
+    #[inline]
+    fn set_clk_high(&mut self) -> Result<(), crate::spi::Error<E>> {
+        self.sck.set_high().map_err(|e| Error::Bus(Line::Sck, e))?;
+        self.tracer.on_clock(true);
+        Ok(())
+    }
+
+    #[inline]
+    fn set_clk_low(&mut self) -> Result<(), crate::spi::Error<E>> {
+        self.sck.set_low().map_err(|e| Error::Bus(Line::Sck, e))?;
+        self.tracer.on_clock(false);
+        Ok(())
+    }
+
+    #[inline]
+    fn wait_for_timer_ticks(&mut self, ticks: u32) {
+        for _ in 0..ticks {
+            self.wait_for_timer();
+        }
+    }
+
+    #[inline]
+    fn wait_for_timer(&mut self) {
+        self.tick_count = self.tick_count.wrapping_add(1);
+        if let Some(timer) = self.timer.as_mut() {
+            half_period_wait(timer);
+        }
+    }
+}
+
+impl<Miso, Mosi, Sck, D, E> SPI<Miso, Mosi, Sck, DelayTimer<D>>
+where
+    Miso: InputPin<Error = E>,
+    Mosi: OutputPin<Error = E>,
+    Sck: OutputPin<Error = E>,
+    D: DelayUs<u32>,
+{
+    /// Create instance clocked by a blocking delay
+    ///
+    /// `half_period_us` is the duration of one clock phase in microseconds,
+    /// i.e. half of the SPI clock period. See [DelayTimer].
+    pub fn new_with_delay(
+        mode: Mode,
+        miso: Miso,
+        mosi: Mosi,
+        sck: Sck,
+        delay: D,
+        half_period_us: u32,
+    ) -> Self {
+        let mut spi = Self::new(
+            mode,
+            miso,
+            mosi,
+            sck,
+            DelayTimer::new(delay, half_period_us),
+        );
+        spi.configured_hz = 1_000_000u32.checked_div(half_period_us).unwrap_or(0);
+        spi
+    }
+}
+
+/// Builder for [SPI] with named setters
+///
+/// Pins and the timer are required; [build](SpiBuilder::build) is only
+/// available once all of them have been set, so a missing pin is a compile
+/// error. The remaining options default to the values used by [SPI::new].
+///
+/// ```ignore
+/// let spi = SpiBuilder::new()
+///     .miso(miso)
+///     .mosi(mosi)
+///     .sck(sck)
+///     .timer(timer)
+///     .mode(MODE_3)
+///     .bit_order(BitOrder::LSBFirst)
+///     .build();
+/// ```
+pub struct SpiBuilder<Miso = (), Mosi = (), Sck = (), Timer = ()> {
+    mode: Mode,
+    miso: Miso,
+    mosi: Mosi,
+    sck: Sck,
+    timer: Timer,
+    bit_order: BitOrder,
+    mosi_idle: Option<bool>,
+    clk_high_ticks: u32,
+    clk_low_ticks: u32,
+    sample_count: u8,
+}
+
+impl SpiBuilder {
+    /// Create a builder without pins and timer, in [MODE_0]
+    pub fn new() -> Self {
+        SpiBuilder {
+            mode: MODE_0,
+            miso: (),
+            mosi: (),
+            sck: (),
+            timer: (),
+            bit_order: BitOrder::default(),
+            mosi_idle: None,
+            clk_high_ticks: 1,
+            clk_low_ticks: 1,
+            sample_count: 1,
+        }
+    }
+}
+
+impl Default for SpiBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<Miso, Mosi, Sck, Timer> SpiBuilder<Miso, Mosi, Sck, Timer> {
+    /// Set the MISO pin
+    pub fn miso<P>(self, miso: P) -> SpiBuilder<P, Mosi, Sck, Timer> {
+        SpiBuilder {
+            mode: self.mode,
+            miso,
+            mosi: self.mosi,
+            sck: self.sck,
+            timer: self.timer,
+            bit_order: self.bit_order,
+            mosi_idle: self.mosi_idle,
+            clk_high_ticks: self.clk_high_ticks,
+            clk_low_ticks: self.clk_low_ticks,
+            sample_count: self.sample_count,
+        }
+    }
+
+    /// Set the MOSI pin
+    pub fn mosi<P>(self, mosi: P) -> SpiBuilder<Miso, P, Sck, Timer> {
+        SpiBuilder {
+            mode: self.mode,
+            miso: self.miso,
+            mosi,
+            sck: self.sck,
+            timer: self.timer,
+            bit_order: self.bit_order,
+            mosi_idle: self.mosi_idle,
+            clk_high_ticks: self.clk_high_ticks,
+            clk_low_ticks: self.clk_low_ticks,
+            sample_count: self.sample_count,
+        }
+    }
+
+    /// Set the SCK pin
+    pub fn sck<P>(self, sck: P) -> SpiBuilder<Miso, Mosi, P, Timer> {
+        SpiBuilder {
+            mode: self.mode,
+            miso: self.miso,
+            mosi: self.mosi,
+            sck,
+            timer: self.timer,
+            bit_order: self.bit_order,
+            mosi_idle: self.mosi_idle,
+            clk_high_ticks: self.clk_high_ticks,
+            clk_low_ticks: self.clk_low_ticks,
+            sample_count: self.sample_count,
+        }
+    }
+
+    /// Set the timer
+    pub fn timer<T>(self, timer: T) -> SpiBuilder<Miso, Mosi, Sck, T> {
+        SpiBuilder {
+            mode: self.mode,
+            miso: self.miso,
+            mosi: self.mosi,
+            sck: self.sck,
+            timer,
+            bit_order: self.bit_order,
+            mosi_idle: self.mosi_idle,
+            clk_high_ticks: self.clk_high_ticks,
+            clk_low_ticks: self.clk_low_ticks,
+            sample_count: self.sample_count,
+        }
+    }
+
+    /// Set the SPI mode
+    pub fn mode(mut self, mode: Mode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Set the transmission bit order, see [SPI::set_bit_order]
+    pub fn bit_order(mut self, order: BitOrder) -> Self {
+        self.bit_order = order;
+        self
+    }
+
+    /// Set the MOSI idle level, see [SPI::set_mosi_idle]
+    pub fn mosi_idle(mut self, level: Option<bool>) -> Self {
+        self.mosi_idle = level;
+        self
+    }
+
+    /// Set the clock phase durations, see [SPI::new_with_clock_ticks]
+    pub fn clock_ticks(mut self, clk_high_ticks: u32, clk_low_ticks: u32) -> Self {
+        self.clk_high_ticks = clk_high_ticks;
+        self.clk_low_ticks = clk_low_ticks;
+        self
+    }
+
+    /// Set the number of MISO reads per bit, see [SPI::set_sample_count]
+    pub fn sample_count(mut self, count: u8) -> Self {
+        self.sample_count = count;
+        self
+    }
+}
+
+impl<Miso, Mosi, Sck, Timer, E> SpiBuilder<Miso, Mosi, Sck, Timer>
+where
+    Miso: InputPin<Error = E>,
+    Mosi: OutputPin<Error = E>,
+    Sck: OutputPin<Error = E>,
+    Timer: CountDown + Periodic,
+{
+    /// Create the [SPI] instance
+    pub fn build(self) -> SPI<Miso, Mosi, Sck, Timer> {
+        let mut spi = SPI::new_with_clock_ticks(
+            self.mode,
+            self.miso,
+            self.mosi,
+            self.sck,
+            self.timer,
+            self.clk_high_ticks,
+            self.clk_low_ticks,
+        );
+        spi.set_bit_order(self.bit_order);
+        spi.set_mosi_idle(self.mosi_idle);
+        spi.set_sample_count(self.sample_count);
+        spi
+    }
+}
+
+/// Shows the mode, bit order and clock timing; pins, timer and tracer are
+/// omitted
+///
+/// `Mode` does not implement `Debug`, so the mode is shown as its number,
+/// 0 to 3.
+impl<Miso, Mosi, Sck, Timer, Tr> core::fmt::Debug for SPI<Miso, Mosi, Sck, Timer, Tr>
+where
+    Miso: InputPin,
+    Mosi: OutputPin,
+    Sck: OutputPin,
+    Timer: CountDown + Periodic,
+    Tr: SpiTrace,
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let polarity = match self.mode.polarity {
+            Polarity::IdleLow => 0,
+            Polarity::IdleHigh => 2,
+        };
+        let phase = match self.mode.phase {
+            Phase::CaptureOnFirstTransition => 0,
+            Phase::CaptureOnSecondTransition => 1,
+        };
+        f.debug_struct("SPI")
+            .field("mode", &(polarity | phase))
+            .field("bit_order", &self.bit_order)
+            .field("mosi_idle", &self.mosi_idle)
+            .field("clk_high_ticks", &self.clk_high_ticks)
+            .field("clk_low_ticks", &self.clk_low_ticks)
+            .field("sample_edge", &self.sample_edge)
+            .field("shift_edge", &self.shift_edge)
+            .field("sample_count", &self.sample_count)
+            .field("fill_byte", &self.fill_byte)
+            .field("miso_settle_ticks", &self.miso_settle_ticks)
+            .field("late_sample_ticks", &self.late_sample_ticks)
+            .field("cs_setup_ticks", &self.cs_setup_ticks)
+            .field("cs_hold_ticks", &self.cs_hold_ticks)
+            .field("configured_hz", &self.configured_hz)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<Miso, Mosi, Sck, Timer, Tr, E> FullDuplex<u8> for SPI<Miso, Mosi, Sck, Timer, Tr>
+where
+    Miso: InputPin<Error = E>,
+    Mosi: OutputPin<Error = E>,
+    Sck: OutputPin<Error = E>,
+    Timer: CountDown + Periodic,
+    Tr: SpiTrace,
+{
+    type Error = crate::spi::Error<E>;
+
+    #[inline]
+    fn read(&mut self) -> nb::Result<u8, Self::Error> {
+        match self.read_val {
+            Some(val) => Ok(val),
+            None => Err(nb::Error::Other(crate::spi::Error::NoData)),
+        }
+    }
+
+    fn send(&mut self, byte: u8) -> nb::Result<(), Self::Error> {
+        self.reset_on_error(|spi| {
+            spi.check_abort()?;
+
+            for bit_offset in 0..8 {
+                let out_bit = match spi.bit_order {
+                    BitOrder::MSBFirst => (byte >> (7 - bit_offset)) & 0b1,
+                    BitOrder::LSBFirst => (byte >> bit_offset) & 0b1,
+                };
+
+                spi.clock_bit(Some(out_bit == 1), true)?;
+            }
+            spi.finish_byte();
+
+            spi.idle_mosi()
+        })?;
+
+        Ok(())
+    }
+}
+
+impl<Miso, Mosi, Sck, Timer, Tr, E> embedded_hal::blocking::spi::transfer::Default<u8>
+    for SPI<Miso, Mosi, Sck, Timer, Tr>
+where
+    Miso: InputPin<Error = E>,
+    Mosi: OutputPin<Error = E>,
+    Sck: OutputPin<Error = E>,
+    Timer: CountDown + Periodic,
+    Tr: SpiTrace,
+{
+}
+
+impl<Miso, Mosi, Sck, Timer, Tr, E> embedded_hal::blocking::spi::write::Default<u8>
+    for SPI<Miso, Mosi, Sck, Timer, Tr>
+where
+    Miso: InputPin<Error = E>,
+    Mosi: OutputPin<Error = E>,
+    Sck: OutputPin<Error = E>,
+    Timer: CountDown + Periodic,
+    Tr: SpiTrace,
+{
+}
+
+/// SPI device with a chip select (CS) pin
+///
+/// CS is asserted (low) around every blocking transfer and write. After
+/// asserting CS the wrapper waits `cs_setup_ticks` timer ticks before the
+/// first clock edge, and after the last clock edge it waits `cs_hold_ticks`
+/// before deasserting CS, to meet the slave's CS setup and hold times.
+pub struct SpiWithCs<Miso, Mosi, Sck, Timer, CS, Tr = ()>
+where
+    Miso: InputPin,
+    Mosi: OutputPin,
+    Sck: OutputPin,
+    Timer: CountDown + Periodic,
+    CS: OutputPin,
+    Tr: SpiTrace,
+{
+    spi: SPI<Miso, Mosi, Sck, Timer, Tr>,
+    cs: CS,
+}
+
+impl<Miso, Mosi, Sck, Timer, CS, Tr, E> SpiWithCs<Miso, Mosi, Sck, Timer, CS, Tr>
+where
+    Miso: InputPin<Error = E>,
+    Mosi: OutputPin<Error = E>,
+    Sck: OutputPin<Error = E>,
+    Timer: CountDown + Periodic,
+    CS: OutputPin<Error = E>,
+    Tr: SpiTrace,
+{
+    /// Create instance, deasserting CS
+    pub fn new(spi: SPI<Miso, Mosi, Sck, Timer, Tr>, mut cs: CS) -> Self {
+        cs.set_high().unwrap_or(());
+        SpiWithCs { spi, cs }
+    }
+
+    /// Set the number of timer ticks between asserting CS and the first clock
+    /// edge. Defaults to zero.
+    pub fn set_cs_setup_ticks(&mut self, ticks: u32) {
+        self.spi.set_cs_setup_ticks(ticks);
+    }
+
+    /// Set the number of timer ticks between the last clock edge and
+    /// deasserting CS. Defaults to zero.
+    pub fn set_cs_hold_ticks(&mut self, ticks: u32) {
+        self.spi.set_cs_hold_ticks(ticks);
+    }
+
+    /// Release the SPI device and the CS pin
+    pub fn free(self) -> (SPI<Miso, Mosi, Sck, Timer, Tr>, CS) {
+        (self.spi, self.cs)
+    }
+
+    /// Run `f` with CS asserted, deasserting it afterwards even if `f` fails
+    fn with_cs<T, F>(&mut self, f: F) -> Result<T, crate::spi::Error<E>>
+    where
+        F: FnOnce(&mut SPI<Miso, Mosi, Sck, Timer, Tr>) -> Result<T, crate::spi::Error<E>>,
+    {
+        self.spi.with_cs_pin(&mut self.cs, f)
+    }
+}
+
+impl<Miso, Mosi, Sck, Timer, CS, Tr, E> embedded_hal::blocking::spi::Transfer<u8>
+    for SpiWithCs<Miso, Mosi, Sck, Timer, CS, Tr>
+where
+    Miso: InputPin<Error = E>,
+    Mosi: OutputPin<Error = E>,
+    Sck: OutputPin<Error = E>,
+    Timer: CountDown + Periodic,
+    CS: OutputPin<Error = E>,
+    Tr: SpiTrace,
+{
+    type Error = crate::spi::Error<E>;
+
+    fn transfer<'w>(&mut self, words: &'w mut [u8]) -> Result<&'w [u8], Self::Error> {
+        self.with_cs(|spi| {
+            for word in words.iter_mut() {
+                block!(spi.send(*word))?;
+                *word = block!(spi.read())?;
+            }
+            Ok(())
+        })?;
+        Ok(words)
+    }
+}
+
+impl<Miso, Mosi, Sck, Timer, CS, Tr, E> embedded_hal::blocking::spi::Write<u8>
+    for SpiWithCs<Miso, Mosi, Sck, Timer, CS, Tr>
+where
+    Miso: InputPin<Error = E>,
+    Mosi: OutputPin<Error = E>,
+    Sck: OutputPin<Error = E>,
+    Timer: CountDown + Periodic,
+    CS: OutputPin<Error = E>,
+    Tr: SpiTrace,
+{
+    type Error = crate::spi::Error<E>;
+
+    fn write(&mut self, words: &[u8]) -> Result<(), Self::Error> {
+        self.with_cs(|spi| {
+            for word in words {
+                block!(spi.send(*word))?;
+            }
+            Ok(())
+        })
+    }
+}
+
+/// SPI device with a clock enable (gate) pin
+///
+/// Some gated-clock devices and display controllers expect a separate enable
+/// line that is asserted only while data is clocked, instead of a chip
+/// select. The enable pin is asserted around every blocking transfer and
+/// write; the clocking itself is unchanged.
+pub struct SpiWithClockEnable<Miso, Mosi, Sck, Timer, EN, Tr = ()>
+where
+    Miso: InputPin,
+    Mosi: OutputPin,
+    Sck: OutputPin,
+    Timer: CountDown + Periodic,
+    EN: OutputPin,
+    Tr: SpiTrace,
+{
+    spi: SPI<Miso, Mosi, Sck, Timer, Tr>,
+    en: EN,
+    active_high: bool,
+}
+
+impl<Miso, Mosi, Sck, Timer, EN, Tr, E> SpiWithClockEnable<Miso, Mosi, Sck, Timer, EN, Tr>
+where
+    Miso: InputPin<Error = E>,
+    Mosi: OutputPin<Error = E>,
+    Sck: OutputPin<Error = E>,
+    Timer: CountDown + Periodic,
+    EN: OutputPin<Error = E>,
+    Tr: SpiTrace,
+{
+    /// Create instance, deasserting the enable pin
+    ///
+    /// With `active_high` the pin is driven high while clocking, otherwise
+    /// low.
+    pub fn new(spi: SPI<Miso, Mosi, Sck, Timer, Tr>, en: EN, active_high: bool) -> Self {
+        let mut spi = SpiWithClockEnable {
+            spi,
+            en,
+            active_high,
+        };
+        spi.set_enable(false).unwrap_or(());
+        spi
+    }
+
+    /// Release the SPI device and the enable pin
+    pub fn free(self) -> (SPI<Miso, Mosi, Sck, Timer, Tr>, EN) {
+        (self.spi, self.en)
+    }
+
+    /// Run `f` with the clock enabled, disabling it afterwards even if `f`
+    /// fails
+    fn with_enable<T, F>(&mut self, f: F) -> Result<T, crate::spi::Error<E>>
+    where
+        F: FnOnce(&mut SPI<Miso, Mosi, Sck, Timer, Tr>) -> Result<T, crate::spi::Error<E>>,
+    {
+        self.set_enable(true)?;
+        let result = f(&mut self.spi);
+        self.set_enable(false)?;
+        result
+    }
+
+    #[inline]
+    fn set_enable(&mut self, enabled: bool) -> Result<(), crate::spi::Error<E>> {
+        if enabled == self.active_high {
+            self.en.set_high().map_err(|e| Error::Bus(Line::Enable, e))
+        } else {
+            self.en.set_low().map_err(|e| Error::Bus(Line::Enable, e))
+        }
+    }
+}
+
+impl<Miso, Mosi, Sck, Timer, EN, Tr, E> embedded_hal::blocking::spi::Transfer<u8>
+    for SpiWithClockEnable<Miso, Mosi, Sck, Timer, EN, Tr>
+where
+    Miso: InputPin<Error = E>,
+    Mosi: OutputPin<Error = E>,
+    Sck: OutputPin<Error = E>,
+    Timer: CountDown + Periodic,
+    EN: OutputPin<Error = E>,
+    Tr: SpiTrace,
+{
+    type Error = crate::spi::Error<E>;
+
+    fn transfer<'w>(&mut self, words: &'w mut [u8]) -> Result<&'w [u8], Self::Error> {
+        self.with_enable(|spi| {
+            for word in words.iter_mut() {
+                block!(spi.send(*word))?;
+                *word = block!(spi.read())?;
+            }
+            Ok(())
+        })?;
+        Ok(words)
+    }
+}
+
+impl<Miso, Mosi, Sck, Timer, EN, Tr, E> embedded_hal::blocking::spi::Write<u8>
+    for SpiWithClockEnable<Miso, Mosi, Sck, Timer, EN, Tr>
+where
+    Miso: InputPin<Error = E>,
+    Mosi: OutputPin<Error = E>,
+    Sck: OutputPin<Error = E>,
+    Timer: CountDown + Periodic,
+    EN: OutputPin<Error = E>,
+    Tr: SpiTrace,
+{
+    type Error = crate::spi::Error<E>;
+
+    fn write(&mut self, words: &[u8]) -> Result<(), Self::Error> {
+        self.with_enable(|spi| {
+            for word in words {
+                block!(spi.send(*word))?;
+            }
+            Ok(())
+        })
+    }
+}
+
+/// SPI slave clocked by an external master
+///
+/// SCK, MOSI and CS are inputs, MISO is driven. No timer is needed, since
+/// every bit follows the master's clock edges according to the configured
+/// mode. This is mainly useful as a test peer for a master device.
+///
+/// The slave busy-waits for each clock edge and only gives up when the
+/// master deasserts CS. A master that stalls with CS asserted would block it
+/// forever, so [set_abort_flag](SpiSlave::set_abort_flag) offers a way out.
+pub struct SpiSlave<Miso, Mosi, Sck, Cs>
+where
+    Miso: OutputPin,
+    Mosi: InputPin,
+    Sck: InputPin,
+    Cs: InputPin,
+{
+    mode: Mode,
+    miso: Miso,
+    mosi: Mosi,
+    sck: Sck,
+    cs: Cs,
+    bit_order: BitOrder,
+    abort: Option<&'static AtomicBool>,
+}
+
+impl<Miso, Mosi, Sck, Cs, E> SpiSlave<Miso, Mosi, Sck, Cs>
+where
+    Miso: OutputPin<Error = E>,
+    Mosi: InputPin<Error = E>,
+    Sck: InputPin<Error = E>,
+    Cs: InputPin<Error = E>,
+{
+    /// Create instance
+    pub fn new(mode: Mode, miso: Miso, mosi: Mosi, sck: Sck, cs: Cs) -> Self {
+        SpiSlave {
+            mode,
+            miso,
+            mosi,
+            sck,
+            cs,
+            bit_order: BitOrder::default(),
+            abort: None,
+        }
+    }
+
+    /// Set transmission bit order
+    pub fn set_bit_order(&mut self, order: BitOrder) {
+        self.bit_order = order;
+    }
+
+    /// Cancel transfers when `flag` is set, e.g. from a watchdog or timer
+    /// interrupt
+    ///
+    /// The flag is checked while waiting for a clock edge, so a transfer
+    /// stuck on a stalled master ends with `Err(Aborted)`. It stays set until
+    /// the application clears it. `None` (the default) disables the check.
+    pub fn set_abort_flag(&mut self, flag: Option<&'static AtomicBool>) {
+        self.abort = flag;
+    }
+
+    /// Release the pins
+    pub fn free(self) -> (Miso, Mosi, Sck, Cs) {
+        (self.miso, self.mosi, self.sck, self.cs)
+    }
+
+    /// Exchange one byte with the master
+    ///
+    /// Returns `WouldBlock` while CS is deasserted (high). Once it is
+    /// asserted, `out` is shifted out on MISO while MOSI is sampled on the
+    /// master's clock edges, and the received byte is returned. If CS is
+    /// deasserted before all eight bits have been clocked, the byte is
+    /// discarded and `WouldBlock` is returned.
+    pub fn transfer(&mut self, out: u8) -> nb::Result<u8, crate::spi::Error<E>> {
+        if self.cs.is_high().map_err(|e| Error::Bus(Line::Cs, e))? {
+            return Err(nb::Error::WouldBlock);
+        }
+
+        let idle = self.mode.polarity == Polarity::IdleHigh;
+        let mut byte = 0;
+
+        for bit_offset in 0..8 {
+            let shift = match self.bit_order {
+                BitOrder::MSBFirst => 7 - bit_offset,
+                BitOrder::LSBFirst => bit_offset,
+            };
+            let out_bit = (out >> shift) & 0b1 == 1;
+
+            let in_bit = match self.mode.phase {
+                Phase::CaptureOnFirstTransition => {
+                    self.set_miso(out_bit)?;
+                    self.wait_for_sck(!idle)?;
+                    let in_bit = self.mosi.is_high().map_err(|e| Error::Bus(Line::Mosi, e))?;
+                    self.wait_for_sck(idle)?;
+                    in_bit
+                }
+                Phase::CaptureOnSecondTransition => {
+                    self.wait_for_sck(!idle)?;
+                    self.set_miso(out_bit)?;
+                    self.wait_for_sck(idle)?;
+                    self.mosi.is_high().map_err(|e| Error::Bus(Line::Mosi, e))?
+                }
+            };
+
+            if in_bit {
+                byte |= 1 << shift;
+            }
+        }
+
+        Ok(byte)
+    }
+
+    /// Wait until SCK is at `level`, giving up if CS is deasserted or the
+    /// abort flag is set
+    fn wait_for_sck(&mut self, level: bool) -> nb::Result<(), crate::spi::Error<E>> {
+        while self.sck.is_high().map_err(|e| Error::Bus(Line::Sck, e))? != level {
+            if self.cs.is_high().map_err(|e| Error::Bus(Line::Cs, e))? {
+                return Err(nb::Error::WouldBlock);
+            }
+            if let Some(flag) = self.abort {
+                if flag.load(Ordering::Relaxed) {
+                    return Err(nb::Error::Other(Error::Aborted));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn set_miso(&mut self, high: bool) -> Result<(), crate::spi::Error<E>> {
+        if high {
+            self.miso.set_high().map_err(|e| Error::Bus(Line::Miso, e))
+        } else {
+            self.miso.set_low().map_err(|e| Error::Bus(Line::Miso, e))
+        }
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use super::*;
+    use crate::mock::{Event, FlakyPin, Line as MockLine, MockPin, MockTimer, Recorder, WavePin};
+    use core::cell::{Cell, RefCell};
+    use embedded_hal::spi::{MODE_0, MODE_1, MODE_2, MODE_3};
+
+    type Rec = RefCell<Recorder<1024>>;
+
+    const SCK: MockLine = MockLine::Other(0);
+    const MOSI: MockLine = MockLine::Other(1);
+    const MISO: MockLine = MockLine::Other(2);
+
+    struct Faults {
+        miso: Cell<Option<u32>>,
+        mosi: Cell<Option<u32>>,
+        sck: Cell<Option<u32>>,
+        cs: Cell<Option<u32>>,
+    }
+
+    impl Faults {
+        fn new() -> Self {
+            Faults {
+                miso: Cell::new(None),
+                mosi: Cell::new(None),
+                sck: Cell::new(None),
+                cs: Cell::new(None),
+            }
+        }
+    }
+
+    type FlakySpi<'a> =
+        SPI<FlakyPin<'a, 1024>, FlakyPin<'a, 1024>, FlakyPin<'a, 1024>, MockTimer<'a, 1024>>;
+
+    fn flaky_spi<'a>(rec: &'a Rec, faults: &'a Faults, mode: Mode) -> FlakySpi<'a> {
+        SPI::new(
+            mode,
+            FlakyPin::new(rec, MISO, &faults.miso),
+            FlakyPin::new(rec, MOSI, &faults.mosi),
+            FlakyPin::new(rec, SCK, &faults.sck),
+            MockTimer::new(rec),
+        )
+    }
+
+    type MockSpi<'a> =
+        SPI<MockPin<'a, 1024>, MockPin<'a, 1024>, MockPin<'a, 1024>, MockTimer<'a, 1024>>;
+
+    fn spi(rec: &Rec, mode: Mode) -> MockSpi<'_> {
+        SPI::new(
+            mode,
+            MockPin::new(rec, MISO),
+            MockPin::new(rec, MOSI),
+            MockPin::new(rec, SCK),
+            MockTimer::new(rec),
+        )
+    }
+
+    /// Decode the bytes sent on MOSI in MODE_0, MSB first, into `out` and
+    /// return their number
+    fn sent(rec: &Rec, out: &mut [u8]) -> usize {
+        let (mut sck, mut mosi) = (false, false);
+        let mut bit_count = 0;
+        for event in rec.borrow().events() {
+            match event.line {
+                SCK => {
+                    if event.level && !sck {
+                        let byte = &mut out[bit_count / 8];
+                        *byte = *byte << 1 | mosi as u8;
+                        bit_count += 1;
+                    }
+                    sck = event.level;
+                }
+                MOSI => mosi = event.level,
+                _ => {}
+            }
+        }
+        bit_count / 8
+    }
+
+    fn queue_bytes(rec: &Rec, bytes: &[u8]) {
+        for byte in bytes {
+            rec.borrow_mut().queue_reads(&bits(*byte));
+        }
+    }
+
+    fn bits(byte: u8) -> [bool; 8] {
+        let mut bits = [false; 8];
+        for (i, bit) in bits.iter_mut().enumerate() {
+            *bit = byte & (0x80 >> i) != 0;
+        }
+        bits
+    }
+
+    fn last_level(rec: &Rec, line: MockLine) -> Option<bool> {
+        rec.borrow()
+            .events()
+            .iter()
+            .rev()
+            .find(|event: &&Event| event.line == line)
+            .map(|event| event.level)
+    }
+
+    #[test]
+    fn usable_after_sck_error() {
+        let rec = RefCell::new(Recorder::new());
+        let faults = Faults::new();
+        let mut spi = flaky_spi(&rec, &faults, MODE_0);
+
+        // fail on the rising edge of the fourth bit
+        faults.sck.set(Some(6));
+        assert!(matches!(
+            nb::block!(spi.send(0x00)),
+            Err(Error::Bus(Line::Sck, ()))
+        ));
+        assert_eq!(last_level(&rec, SCK), Some(false));
+        assert!(matches!(spi.read(), Err(nb::Error::Other(Error::NoData))));
+        assert_eq!(spi.take_partial(), (0, 0));
+
+        rec.borrow_mut().queue_reads(&bits(0x3c));
+        nb::block!(spi.send(0x12)).unwrap();
+        assert_eq!(nb::block!(spi.read()).unwrap(), 0x3c);
+        assert_eq!(last_level(&rec, SCK), Some(false));
+    }
+
+    /// Queue `byte` on MISO in LSB-first wire order
+    fn queue_lsb_first(rec: &Rec, byte: u8) {
+        rec.borrow_mut().queue_reads(&bits(byte.reverse_bits()));
+    }
+
+    #[test]
+    fn read_with_fill_lsb_first() {
+        let rec = RefCell::new(Recorder::new());
+        let mut spi = spi(&rec, MODE_0);
+        spi.set_bit_order(BitOrder::LSBFirst);
+        rec.borrow_mut().clear();
+        for byte in [0x01, 0x3c, 0xa0] {
+            queue_lsb_first(&rec, byte);
+        }
+
+        let mut buf = [0; 3];
+        spi.read_with_fill(&mut buf, 0x0f).unwrap();
+        assert_eq!(buf, [0x01, 0x3c, 0xa0]);
+
+        // the fill byte goes out LSB first as well
+        let mut out = [0; 3];
+        assert_eq!(sent(&rec, &mut out), 3);
+        assert_eq!(out, [0xf0; 3]);
+    }
+
+    #[test]
+    fn transfer_array_lsb_first() {
+        let rec = RefCell::new(Recorder::new());
+        let mut spi = spi(&rec, MODE_0);
+        spi.set_bit_order(BitOrder::LSBFirst);
+        rec.borrow_mut().clear();
+        queue_lsb_first(&rec, 0x81);
+        queue_lsb_first(&rec, 0x06);
+
+        assert_eq!(spi.transfer_array([0x12, 0x34]).unwrap(), [0x81, 0x06]);
+        let mut out = [0; 2];
+        assert_eq!(sent(&rec, &mut out), 2);
+        assert_eq!(out, [0x48, 0x2c]);
+    }
+
+    #[test]
+    fn new_with_order_lsb_first() {
+        let rec = RefCell::new(Recorder::new());
+        let mut spi = SPI::new_with_order(
+            MODE_0,
+            MockPin::new(&rec, MISO),
+            MockPin::new(&rec, MOSI),
+            MockPin::new(&rec, SCK),
+            MockTimer::new(&rec),
+            BitOrder::LSBFirst,
+        );
+        rec.borrow_mut().clear();
+        queue_lsb_first(&rec, 0xc5);
+
+        nb::block!(spi.send(0x0e)).unwrap();
+        assert_eq!(nb::block!(spi.read()).unwrap(), 0xc5);
+        let mut out = [0];
+        assert_eq!(sent(&rec, &mut out), 1);
+        assert_eq!(out, [0x70]);
+    }
+
+    #[test]
+    fn take_partial_after_transfer_bit() {
+        let rec = RefCell::new(Recorder::new());
+        let mut spi = spi(&rec, MODE_0);
+        rec.borrow_mut().clear();
+        rec.borrow_mut().queue_reads(&[true, false, true]);
+        assert!(spi.transfer_bit(false).unwrap());
+        assert!(!spi.transfer_bit(true).unwrap());
+        assert!(spi.transfer_bit(false).unwrap());
+        assert_eq!(spi.take_partial(), (0b101, 3));
+        assert_eq!(spi.take_partial(), (0, 0));
+
+        // only the last eight bits are kept
+        rec.borrow_mut().queue_reads(&[
+            true, false, false, false, false, false, false, false, true, true,
+        ]);
+        for _ in 0..10 {
+            spi.transfer_bit(false).unwrap();
+        }
+        assert_eq!(spi.take_partial(), (0b0000_0011, 8));
+    }
+
+    /// Three MISO samples per bit of `byte`, MSB first, with one of each
+    /// three flipped
+    fn noisy_bits(byte: u8) -> [bool; 24] {
+        let mut samples = [false; 24];
+        for (i, sample) in samples.iter_mut().enumerate() {
+            let bit = byte & (0x80 >> (i / 3)) != 0;
+            *sample = bit ^ (i % 3 == i / 3 % 3);
+        }
+        samples
+    }
+
+    #[test]
+    fn sample_count_outvotes_one_sample() {
+        let rec = RefCell::new(Recorder::new());
+        let mut spi = spi(&rec, MODE_0);
+        spi.set_sample_count(3);
+        rec.borrow_mut().clear();
+        rec.borrow_mut().queue_reads(&noisy_bits(0xa5));
+        rec.borrow_mut().queue_reads(&noisy_bits(0x3c));
+        assert_eq!(spi.transfer_array([0x00, 0x00]).unwrap(), [0xa5, 0x3c]);
+    }
+
+    #[test]
+    fn transfer_split_short_write() {
+        let rec = RefCell::new(Recorder::new());
+        let mut spi = spi(&rec, MODE_0);
+        spi.set_fill_byte(0xff);
+        queue_bytes(&rec, &[0x11, 0x22, 0x33]);
+        let mut read = [0; 3];
+        spi.transfer_split(&[0xa1], &mut read).unwrap();
+        assert_eq!(read, [0x11, 0x22, 0x33]);
+        let mut mosi = [0; 4];
+        assert_eq!(sent(&rec, &mut mosi), 3);
+        assert_eq!(mosi[..3], [0xa1, 0xff, 0xff]);
+    }
+
+    #[test]
+    fn transfer_split_equal_lengths() {
+        let rec = RefCell::new(Recorder::new());
+        let mut spi = spi(&rec, MODE_0);
+        queue_bytes(&rec, &[0x11, 0x22]);
+        let mut read = [0; 2];
+        spi.transfer_split(&[0xa1, 0xb2], &mut read).unwrap();
+        assert_eq!(read, [0x11, 0x22]);
+        let mut mosi = [0; 4];
+        assert_eq!(sent(&rec, &mut mosi), 2);
+        assert_eq!(mosi[..2], [0xa1, 0xb2]);
+    }
+
+    #[test]
+    fn transfer_split_long_write() {
+        let rec = RefCell::new(Recorder::new());
+        let mut spi = spi(&rec, MODE_0);
+        queue_bytes(&rec, &[0x11, 0x22, 0x33]);
+        let mut read = [0; 1];
+        spi.transfer_split(&[0xa1, 0xb2, 0xc3], &mut read).unwrap();
+        assert_eq!(read, [0x11]);
+        // the discarded bytes were still clocked in
+        assert_eq!(spi.read().ok(), Some(0x33));
+        let mut mosi = [0; 4];
+        assert_eq!(sent(&rec, &mut mosi), 3);
+        assert_eq!(mosi[..3], [0xa1, 0xb2, 0xc3]);
+    }
+
+    /// Records the time of every MISO sample
+    struct SampleTimes<'a> {
+        rec: &'a Rec,
+        times: &'a RefCell<[u32; 8]>,
+        count: usize,
+    }
+
+    impl SpiTrace for SampleTimes<'_> {
+        fn on_miso(&mut self, _bit: bool) {
+            self.times.borrow_mut()[self.count] = self.rec.borrow().time();
+            self.count += 1;
+        }
+    }
+
+    /// Run one byte with 4 ticks per clock phase and return the time of each
+    /// sample relative to the preceding sampling edge, and the total time
+    fn sample_delays(mode: Mode, late_ticks: u32) -> ([u32; 8], u32) {
+        let rec = RefCell::new(Recorder::new());
+        let times = RefCell::new([0; 8]);
+        let mut spi = SPI::new_with_clock_ticks(
+            mode,
+            MockPin::new(&rec, MISO),
+            MockPin::new(&rec, MOSI),
+            MockPin::new(&rec, SCK),
+            MockTimer::new(&rec),
+            4,
+            4,
+        )
+        .with_tracer(SampleTimes {
+            rec: &rec,
+            times: &times,
+            count: 0,
+        });
+        spi.set_late_sample_ticks(late_ticks);
+        nb::block!(spi.send(0xa5)).unwrap();
+
+        // MODE_0 samples on the rising edge, MODE_1 on the falling edge
+        let sample_level = mode == MODE_0;
+        let mut sck = false;
+        let mut edges = [0; 8];
+        let mut count = 0;
+        for event in rec.borrow().events().iter().filter(|e| e.line == SCK) {
+            if event.level != sck && event.level == sample_level {
+                edges[count] = event.time;
+                count += 1;
+            }
+            sck = event.level;
+        }
+        assert_eq!(count, 8);
+
+        let mut delays = times.into_inner();
+        for (delay, edge) in delays.iter_mut().zip(edges) {
+            *delay -= edge;
+        }
+        let time = rec.borrow().time();
+        (delays, time)
+    }
+
+    #[test]
+    fn late_sample_on_leading_edge() {
+        let (_, time) = sample_delays(MODE_0, 0);
+        assert_eq!(sample_delays(MODE_0, 0), ([0; 8], time));
+        assert_eq!(sample_delays(MODE_0, 2), ([2; 8], time));
+        // limited to the active clock phase
+        assert_eq!(sample_delays(MODE_0, 9), ([4; 8], time));
+    }
+
+    #[test]
+    fn late_sample_on_trailing_edge() {
+        let (_, time) = sample_delays(MODE_1, 0);
+        assert_eq!(sample_delays(MODE_1, 0), ([0; 8], time));
+        assert_eq!(sample_delays(MODE_1, 3), ([3; 8], time));
+        // limited to the idle clock phase
+        assert_eq!(sample_delays(MODE_1, 9), ([4; 8], time));
+    }
+
+    #[test]
+    fn two_ticks_per_bit_in_every_mode() {
+        for mode in [MODE_0, MODE_1, MODE_2, MODE_3] {
+            let rec = RefCell::new(Recorder::new());
+            let mut spi = spi(&rec, mode);
+            for byte in 1..=3 {
+                nb::block!(spi.send(0xa5)).unwrap();
+                assert_eq!(rec.borrow().time(), 16 * byte);
+                assert_eq!(spi.take_tick_count(), 16);
+            }
+        }
+    }
+
+    /// Read 0x55 in MODE_0 from a slave with an open-drain MISO that rises
+    /// `rise_ticks` after being released on the falling SCK edge
+    fn read_open_drain(rise_ticks: u32, settle_ticks: u32) -> u8 {
+        let rec: Rec = RefCell::new(Recorder::new());
+        let miso = |tick| {
+            let rec = rec.borrow();
+            let (mut sck, mut shifts, mut released_at) = (false, 0, 0);
+            for event in rec
+                .events()
+                .iter()
+                .filter(|e| e.line == SCK && e.time <= tick)
+            {
+                if sck && !event.level {
+                    shifts += 1;
+                    released_at = event.time;
+                }
+                sck = event.level;
+            }
+            let bit = shifts < 8 && 0x55u8 << shifts & 0x80 != 0;
+            bit && tick >= released_at + rise_ticks
+        };
+        let mut spi = SPI::new(
+            MODE_0,
+            WavePin::new(&rec, MISO, &miso),
+            MockPin::new(&rec, MOSI),
+            MockPin::new(&rec, SCK),
+            MockTimer::new(&rec),
+        );
+        spi.set_miso_settle_ticks(settle_ticks);
+        nb::block!(spi.send(0x00)).unwrap();
+        nb::block!(spi.read()).unwrap()
+    }
+
+    #[test]
+    fn miso_settle_ticks_wait_for_pull_up() {
+        // the idle clock phase already gives the line one tick to rise
+        for rise_ticks in 1..=4 {
+            for settle_ticks in 0..=4 {
+                let expected = if settle_ticks + 1 >= rise_ticks {
+                    0x55
+                } else {
+                    0x00
+                };
+                assert_eq!(
+                    read_open_drain(rise_ticks, settle_ticks),
+                    expected,
+                    "rise {} settle {}",
+                    rise_ticks,
+                    settle_ticks
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn bus_error_reports_line() {
+        for line in [Line::Sck, Line::Mosi, Line::Miso, Line::Cs] {
+            let rec = RefCell::new(Recorder::new());
+            let faults = Faults::new();
+            let mut spi = SpiWithCs::new(
+                flaky_spi(&rec, &faults, MODE_0),
+                FlakyPin::new(&rec, MockLine::Other(3), &faults.cs),
+            );
+            let fault = match line {
+                Line::Sck => &faults.sck,
+                Line::Mosi => &faults.mosi,
+                Line::Miso => &faults.miso,
+                Line::Cs => &faults.cs,
+                Line::Enable => unreachable!(),
+            };
+            fault.set(Some(0));
+            let mut words = [0xa5];
+            let result = embedded_hal::blocking::spi::Transfer::transfer(&mut spi, &mut words);
+            assert!(
+                matches!(result, Err(Error::Bus(failed, ())) if failed == line),
+                "{:?}",
+                line
+            );
+        }
+    }
+
+    const CS: MockLine = MockLine::Other(3);
+
+    /// Ticks from asserting CS to the first SCK edge, and from the last SCK
+    /// edge to deasserting CS, for a one byte write in MODE_0
+    fn cs_timing(setup_ticks: u32, hold_ticks: u32) -> (u32, u32) {
+        let rec = RefCell::new(Recorder::new());
+        let mut spi = SpiWithCs::new(spi(&rec, MODE_0), MockPin::new(&rec, CS));
+        spi.set_cs_setup_ticks(setup_ticks);
+        spi.set_cs_hold_ticks(hold_ticks);
+        rec.borrow_mut().clear();
+        embedded_hal::blocking::spi::Write::write(&mut spi, &[0xa5]).unwrap();
+        cs_gaps(&rec)
+    }
+
+    /// The same for probing a single slave with
+    /// [probe_slaves](SPI::probe_slaves)
+    fn probe_cs_timing(setup_ticks: u32, hold_ticks: u32) -> (u32, u32) {
+        let rec = RefCell::new(Recorder::new());
+        let mut spi = spi(&rec, MODE_0);
+        let mut cs = MockPin::new(&rec, CS);
+        spi.set_cs_setup_ticks(setup_ticks);
+        spi.set_cs_hold_ticks(hold_ticks);
+        rec.borrow_mut().clear();
+        spi.probe_slaves(&mut [&mut cs], 0xa5, &mut [0]).unwrap();
+        cs_gaps(&rec)
+    }
+
+    fn cs_gaps(rec: &Rec) -> (u32, u32) {
+        let rec = rec.borrow();
+        let time_of = |line, level| {
+            rec.events()
+                .iter()
+                .filter(move |e: &&Event| e.line == line && e.level == level)
+                .map(|e| e.time)
+        };
+        let cs_low = time_of(CS, false).next().unwrap();
+        let cs_high = time_of(CS, true).next_back().unwrap();
+        let first_sck = time_of(SCK, true).next().unwrap();
+        let last_sck = time_of(SCK, false).next_back().unwrap();
+        (first_sck - cs_low, cs_high - last_sck)
+    }
+
+    #[test]
+    fn cs_setup_and_hold_ticks() {
+        // the first bit starts with an idle clock phase of one tick
+        assert_eq!(cs_timing(0, 0), (1, 0));
+        assert_eq!(cs_timing(3, 0), (4, 0));
+        assert_eq!(cs_timing(0, 2), (1, 2));
+        assert_eq!(cs_timing(3, 2), (4, 2));
+
+        // probe_slaves adds the idle clock phase of begin_transfer
+        assert_eq!(probe_cs_timing(0, 0), (2, 0));
+        assert_eq!(probe_cs_timing(3, 0), (5, 0));
+        assert_eq!(probe_cs_timing(0, 2), (2, 2));
+        assert_eq!(probe_cs_timing(3, 2), (5, 2));
+    }
+
+    /// Master clocking one byte into an [SpiSlave], MSB first
+    ///
+    /// Every read of SCK advances the clock by one edge, so the slave sees
+    /// each edge exactly once. MISO is sampled from the recording on the
+    /// sampling edges of the mode.
+    struct Master<'a> {
+        rec: &'a Rec,
+        mode: Mode,
+        out: u8,
+        edges: Cell<u32>,
+        received: Cell<u8>,
+    }
+
+    impl Master<'_> {
+        fn sck(&self) -> bool {
+            let edges = (self.edges.get() + 1).min(16);
+            if edges != self.edges.get() {
+                self.edges.set(edges);
+                let leading = edges % 2 == 1;
+                let sample_edge = match self.mode.phase {
+                    Phase::CaptureOnFirstTransition => leading,
+                    Phase::CaptureOnSecondTransition => !leading,
+                };
+                if sample_edge {
+                    let miso = last_level(self.rec, MISO).unwrap_or(true);
+                    self.received.set(self.received.get() << 1 | miso as u8);
+                }
+            }
+            (self.mode.polarity == Polarity::IdleHigh) ^ (edges % 2 == 1)
+        }
+
+        fn mosi(&self) -> bool {
+            // the bit being shifted out, changed on the shift edges
+            let bit = match self.mode.phase {
+                Phase::CaptureOnFirstTransition => self.edges.get() / 2,
+                Phase::CaptureOnSecondTransition => self.edges.get().saturating_sub(1) / 2,
+            };
+            self.out & (0x80 >> bit.min(7)) != 0
+        }
+    }
+
+    struct MasterPin<'a>(&'a Master<'a>, Line);
+
+    impl InputPin for MasterPin<'_> {
+        type Error = core::convert::Infallible;
+
+        fn is_high(&self) -> Result<bool, Self::Error> {
+            Ok(match self.1 {
+                Line::Sck => self.0.sck(),
+                Line::Mosi => self.0.mosi(),
+                _ => self.0.edges.get() == 16,
+            })
+        }
+
+        fn is_low(&self) -> Result<bool, Self::Error> {
+            self.is_high().map(|high| !high)
+        }
+    }
+
+    #[test]
+    fn slave_in_every_mode() {
+        for (number, mode) in [MODE_0, MODE_1, MODE_2, MODE_3].into_iter().enumerate() {
+            for order in [BitOrder::MSBFirst, BitOrder::LSBFirst] {
+                let rec = RefCell::new(Recorder::new());
+                let master = Master {
+                    rec: &rec,
+                    mode,
+                    out: 0x96,
+                    edges: Cell::new(0),
+                    received: Cell::new(0),
+                };
+                let mut slave = SpiSlave::new(
+                    mode,
+                    MockPin::new(&rec, MISO),
+                    MasterPin(&master, Line::Mosi),
+                    MasterPin(&master, Line::Sck),
+                    MasterPin(&master, Line::Cs),
+                );
+                slave.set_bit_order(order);
+
+                let (expected, sent) = match order {
+                    BitOrder::MSBFirst => (0x96, 0x3c),
+                    BitOrder::LSBFirst => (0x69, 0x3c_u8.reverse_bits()),
+                };
+                let result = slave.transfer(0x3c).ok();
+                assert_eq!(result, Some(expected), "mode {} {:?}", number, order);
+                assert_eq!(master.received.get(), sent, "mode {} {:?}", number, order);
+                assert_eq!(master.edges.get(), 16);
+                assert!(matches!(slave.transfer(0x3c), Err(nb::Error::WouldBlock)));
+            }
+        }
+    }
+
+    #[test]
+    fn slave_aborts_on_stalled_master() {
+        static ABORT: AtomicBool = AtomicBool::new(false);
+        let rec: Rec = RefCell::new(Recorder::new());
+        let mut cs = MockPin::new(&rec, MockLine::Other(3));
+        cs.set_low().unwrap();
+        // SCK stays high, as if the master stopped in the middle of a byte
+        let mut slave = SpiSlave::new(
+            MODE_0,
+            MockPin::new(&rec, MISO),
+            MockPin::new(&rec, MOSI),
+            MockPin::new(&rec, SCK),
+            cs,
+        );
+        slave.set_abort_flag(Some(&ABORT));
+        ABORT.store(true, Ordering::Relaxed);
+        assert!(matches!(
+            slave.transfer(0x00),
+            Err(nb::Error::Other(Error::Aborted))
+        ));
+    }
+
+    #[test]
+    fn sck_idle_between_calls() {
+        for mode in [MODE_0, MODE_1, MODE_2, MODE_3] {
+            let idle = mode.polarity == Polarity::IdleHigh;
+            let rec = RefCell::new(Recorder::new());
+            let mut spi = spi(&rec, mode);
+            spi.begin_transfer().unwrap();
+            for byte in [0x12, 0x34, 0x56] {
+                nb::block!(spi.send(byte)).unwrap();
+                assert_eq!(last_level(&rec, SCK), Some(idle));
+            }
+            spi.end_transfer().unwrap();
+
+            // eight clean pulses per byte and no edge between the calls
+            let mut level = idle;
+            let mut edges = 0;
+            for event in rec.borrow().events().iter().filter(|e| e.line == SCK) {
+                if event.level != level {
+                    edges += 1;
+                    level = event.level;
+                }
+            }
+            assert_eq!(edges, 3 * 16);
+            assert_eq!(level, idle);
+        }
+    }
 }
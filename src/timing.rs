@@ -0,0 +1,334 @@
+//! Timing adapters
+//!
+//! The drivers in this crate are clocked by a periodic [CountDown] timer.
+//! [DelayTimer] adapts a blocking delay to that interface for HALs that do
+//! not expose a suitable timer, and [ClosureTimer] runs arbitrary code, such
+//! as a cycle-counted busy wait, as each tick. [SharedTimer] lets several
+//! drivers take turns with one timer.
+//!
+//! [half_period_wait] is the wait the drivers use between pin changes, for
+//! building other bit banged protocols on the same timers.
+//!
+//! A generic [CountDown] does not report its frequency, so the drivers
+//! cannot check or report their bus speed on their own. The `new_checked`
+//! constructors take the frequency the timer was started with, check it
+//! against the intended bus speed and record it, and `set_configured_hz`
+//! records it later; `protocol_hz` then reports the resulting bus speed,
+//! e.g. for logging it at startup. `new_with_delay` records the rate
+//! implied by its period.
+//!
+
+use core::cell::RefCell;
+
+use embedded_hal::blocking::delay::DelayUs;
+use embedded_hal::digital::v2::OutputPin;
+use embedded_hal::timer::{CountDown, Periodic};
+use nb::block;
+use void::Void;
+
+/// Block until the next timer tick
+#[inline]
+pub fn half_period_wait<T>(timer: &mut T)
+where
+    T: CountDown,
+{
+    // `CountDown::wait` has an uninhabited error type, so there is no timer
+    // error to report here
+    if let Err(void) = block!(timer.wait()) {
+        match void {}
+    }
+}
+
+/// Drive `pin` to `high` and hold it for `ticks` timer ticks
+///
+/// Used by the output-only drivers, which own their timer and report pin
+/// errors as is. The I2C, SPI and serial drivers only share
+/// [half_period_wait]: their timer can be taken out for reconfiguration,
+/// they count or trace every tick and edge, and their errors name the line.
+#[inline]
+pub(crate) fn clock_edge<P, T>(
+    pin: &mut P,
+    high: bool,
+    timer: &mut T,
+    ticks: u32,
+) -> Result<(), P::Error>
+where
+    P: OutputPin,
+    T: CountDown,
+{
+    if high {
+        pin.set_high()?;
+    } else {
+        pin.set_low()?;
+    }
+    for _ in 0..ticks {
+        half_period_wait(timer);
+    }
+    Ok(())
+}
+
+/// Whether `actual_hz` is within 10 % of `expected_hz`, for the
+/// `new_checked` constructors
+pub(crate) fn close_to(actual_hz: u32, expected_hz: u32) -> bool {
+    let actual = actual_hz as u64 * 10;
+    let expected = expected_hz as u64;
+    actual >= expected * 9 && actual <= expected * 11
+}
+
+/// Periodic timer built on a blocking delay
+///
+/// Every [wait](CountDown::wait) blocks for the configured period in
+/// microseconds. Unlike a hardware timer the delay starts when `wait` is
+/// called, so the time spent toggling pins between waits adds to each period
+/// and the effective bus frequency is somewhat lower than configured.
+pub struct DelayTimer<D> {
+    delay: D,
+    period_us: u32,
+}
+
+impl<D> DelayTimer<D>
+where
+    D: DelayUs<u32>,
+{
+    /// Create instance waiting `period_us` microseconds per tick
+    pub fn new(delay: D, period_us: u32) -> Self {
+        DelayTimer { delay, period_us }
+    }
+
+    /// Release the delay
+    pub fn free(self) -> D {
+        self.delay
+    }
+}
+
+impl<D> CountDown for DelayTimer<D>
+where
+    D: DelayUs<u32>,
+{
+    /// Period in microseconds
+    type Time = u32;
+
+    fn start<T>(&mut self, count: T)
+    where
+        T: Into<u32>,
+    {
+        self.period_us = count.into();
+    }
+
+    fn wait(&mut self) -> nb::Result<(), Void> {
+        self.delay.delay_us(self.period_us);
+        Ok(())
+    }
+}
+
+impl<D> Periodic for DelayTimer<D> where D: DelayUs<u32> {}
+
+/// Periodic timer calling a closure on every tick
+///
+/// Each [wait](CountDown::wait) calls the closure, which is expected to
+/// block for one tick, e.g. `|| cortex_m::asm::delay(CYCLES_PER_TICK)`.
+/// [start](CountDown::start) does nothing, so the closure alone defines the
+/// tick length.
+pub struct ClosureTimer<F> {
+    f: F,
+}
+
+impl<F> ClosureTimer<F>
+where
+    F: FnMut(),
+{
+    /// Create instance calling `f` on every tick
+    pub fn new(f: F) -> Self {
+        ClosureTimer { f }
+    }
+
+    /// Release the closure
+    pub fn free(self) -> F {
+        self.f
+    }
+}
+
+impl<F> CountDown for ClosureTimer<F>
+where
+    F: FnMut(),
+{
+    type Time = ();
+
+    fn start<T>(&mut self, _count: T)
+    where
+        T: Into<()>,
+    {
+    }
+
+    fn wait(&mut self) -> nb::Result<(), Void> {
+        (self.f)();
+        Ok(())
+    }
+}
+
+impl<F> Periodic for ClosureTimer<F> where F: FnMut() {}
+
+/// Handle to a timer shared by several drivers
+///
+/// Each driver gets its own `SharedTimer` borrowing the same [RefCell], e.g.
+/// to run a bitbang SPI and a bitbang UART from one hardware timer:
+///
+/// ```ignore
+/// let timer = RefCell::new(timer);
+/// let mut spi = SPI::new(MODE_0, miso, mosi, sck, SharedTimer::new(&timer));
+/// let mut serial = Serial::new(tx, rx, SharedTimer::new(&timer));
+/// ```
+///
+/// **The drivers must not be used concurrently.** The timer is borrowed for
+/// every [start](CountDown::start) and [wait](CountDown::wait), which panics
+/// if it is already borrowed, e.g. when a driver is used from an interrupt
+/// handler while another one is waiting. All drivers run at the tick rate
+/// the timer was started with.
+pub struct SharedTimer<'a, T> {
+    timer: &'a RefCell<T>,
+}
+
+impl<'a, T> SharedTimer<'a, T>
+where
+    T: CountDown + Periodic,
+{
+    /// Create a handle to `timer`
+    pub fn new(timer: &'a RefCell<T>) -> Self {
+        SharedTimer { timer }
+    }
+}
+
+impl<T> CountDown for SharedTimer<'_, T>
+where
+    T: CountDown + Periodic,
+{
+    type Time = T::Time;
+
+    fn start<C>(&mut self, count: C)
+    where
+        C: Into<T::Time>,
+    {
+        self.timer.borrow_mut().start(count);
+    }
+
+    fn wait(&mut self) -> nb::Result<(), Void> {
+        self.timer.borrow_mut().wait()
+    }
+}
+
+impl<T> Periodic for SharedTimer<'_, T> where T: CountDown + Periodic {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::cell::Cell;
+
+    /// Timer that is not yet expired for `pending` polls before every tick
+    struct SlowTimer {
+        pending: u32,
+        left: u32,
+        polls: u32,
+        ticks: u32,
+    }
+
+    impl SlowTimer {
+        fn new(pending: u32) -> Self {
+            SlowTimer {
+                pending,
+                left: pending,
+                polls: 0,
+                ticks: 0,
+            }
+        }
+    }
+
+    impl CountDown for SlowTimer {
+        type Time = u32;
+
+        fn start<T>(&mut self, _count: T)
+        where
+            T: Into<u32>,
+        {
+        }
+
+        fn wait(&mut self) -> nb::Result<(), Void> {
+            self.polls += 1;
+            if self.left == 0 {
+                self.left = self.pending;
+                self.ticks += 1;
+                Ok(())
+            } else {
+                self.left -= 1;
+                Err(nb::Error::WouldBlock)
+            }
+        }
+    }
+
+    impl Periodic for SlowTimer {}
+
+    struct Delay<'a> {
+        total_us: &'a Cell<u32>,
+    }
+
+    impl DelayUs<u32> for Delay<'_> {
+        fn delay_us(&mut self, us: u32) {
+            self.total_us.set(self.total_us.get() + us);
+        }
+    }
+
+    #[test]
+    fn half_period_wait_polls_until_tick() {
+        let mut timer = SlowTimer::new(2);
+        half_period_wait(&mut timer);
+        assert_eq!((timer.polls, timer.ticks), (3, 1));
+        half_period_wait(&mut timer);
+        assert_eq!((timer.polls, timer.ticks), (6, 2));
+    }
+
+    #[test]
+    fn delay_timer_waits_one_period_per_tick() {
+        let total_us = Cell::new(0);
+        let mut timer = DelayTimer::new(
+            Delay {
+                total_us: &total_us,
+            },
+            5,
+        );
+        half_period_wait(&mut timer);
+        half_period_wait(&mut timer);
+        assert_eq!(total_us.get(), 10);
+        timer.start(20u32);
+        half_period_wait(&mut timer);
+        assert_eq!(total_us.get(), 30);
+    }
+
+    #[test]
+    fn closure_timer_calls_closure_per_tick() {
+        let ticks = Cell::new(0);
+        let mut timer = ClosureTimer::new(|| ticks.set(ticks.get() + 1));
+        for _ in 0..3 {
+            half_period_wait(&mut timer);
+        }
+        assert_eq!(ticks.get(), 3);
+    }
+
+    #[test]
+    fn shared_timer_handles_use_one_timer() {
+        let timer = RefCell::new(SlowTimer::new(1));
+        let mut a = SharedTimer::new(&timer);
+        let mut b = SharedTimer::new(&timer);
+        half_period_wait(&mut a);
+        half_period_wait(&mut b);
+        half_period_wait(&mut a);
+        assert_eq!(timer.borrow().ticks, 3);
+    }
+
+    #[test]
+    fn close_to_allows_ten_percent() {
+        assert!(close_to(90, 100));
+        assert!(close_to(110, 100));
+        assert!(!close_to(89, 100));
+        assert!(!close_to(111, 100));
+        assert!(close_to(0, 0));
+    }
+}
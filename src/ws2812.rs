@@ -0,0 +1,103 @@
+//! WS2812 (NeoPixel) LED driver
+//!
+//! This implementation consumes the following hardware resources:
+//! - Periodic timer to mark pulse widths
+//! - Output GPIO pin for the data line (DIN)
+//!
+//! The timer must be configured to 2.4 MHz, three times the 800 kHz WS2812
+//! bit rate. Each bit is then encoded as three ticks: a 0 is one tick high and
+//! two ticks low, a 1 is two ticks high and one tick low.
+//!
+//! The bit timing is tight, so interrupts should be disabled while writing.
+//!
+
+use embedded_hal::digital::v2::OutputPin;
+use embedded_hal::timer::{CountDown, Periodic};
+
+use crate::timing::{clock_edge, half_period_wait};
+
+/// Number of timer ticks the line is held low to latch the data (> 50 µs)
+const RESET_TICKS: u32 = 125;
+
+/// WS2812 error
+#[derive(Debug)]
+pub enum Error<E> {
+    /// GPIO error
+    Bus(E),
+}
+
+impl<E> core::fmt::Display for Error<E>
+where
+    E: core::fmt::Debug,
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Error::Bus(e) => crate::error::fmt_bus_error(f, None, e),
+        }
+    }
+}
+
+#[cfg(feature = "core-error")]
+impl<E> core::error::Error for Error<E>
+where
+    E: core::error::Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            Error::Bus(e) => Some(e),
+        }
+    }
+}
+
+/// Bit banging WS2812 driver
+pub struct Ws2812<OUT, Timer>
+where
+    OUT: OutputPin,
+    Timer: CountDown + Periodic,
+{
+    out: OUT,
+    timer: Timer,
+}
+
+impl<OUT, Timer, E> Ws2812<OUT, Timer>
+where
+    OUT: OutputPin<Error = E>,
+    Timer: CountDown + Periodic,
+{
+    /// Create instance
+    pub fn new(mut out: OUT, timer: Timer) -> Self {
+        out.set_low().unwrap_or(());
+        Ws2812 { out, timer }
+    }
+
+    /// Send raw bytes, MSB first, followed by the reset (latch) period
+    ///
+    /// Bytes are sent as given, so each LED expects three bytes in
+    /// green, red, blue order.
+    pub fn write(&mut self, data: &[u8]) -> Result<(), crate::ws2812::Error<E>> {
+        for byte in data {
+            for bit_offset in 0..8 {
+                let high_ticks = if (byte >> (7 - bit_offset)) & 0b1 == 1 {
+                    2
+                } else {
+                    1
+                };
+
+                clock_edge(&mut self.out, true, &mut self.timer, high_ticks).map_err(Error::Bus)?;
+                clock_edge(&mut self.out, false, &mut self.timer, 3 - high_ticks)
+                    .map_err(Error::Bus)?;
+            }
+        }
+
+        self.wait_for_timer_ticks(RESET_TICKS);
+
+        Ok(())
+    }
+
+    #[inline]
+    fn wait_for_timer_ticks(&mut self, ticks: u32) {
+        for _ in 0..ticks {
+            half_period_wait(&mut self.timer);
+        }
+    }
+}